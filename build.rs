@@ -0,0 +1,40 @@
+//! Record which commit the server was built from, for `--version` and the
+//! startup banner. Builds outside a git checkout report `00000000`, like
+//! Redis does.
+
+use std::{path::Path, process::Command};
+
+fn main() {
+    let sha1 = git(&["rev-parse", "HEAD"])
+        .map(|sha| sha.chars().take(8).collect())
+        .unwrap_or_else(|| "00000000".to_owned());
+    let dirty = git(&["diff", "--no-ext-diff", "HEAD"]).is_some_and(|diff| !diff.is_empty());
+
+    println!("cargo:rustc-env=REDIS_GIT_SHA1={}", sha1);
+    println!("cargo:rustc-env=REDIS_GIT_DIRTY={}", u8::from(dirty));
+
+    // Rebuild when the checked out commit or the index changes
+    for path in [".git/HEAD", ".git/index", ".git/refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+
+    // ...and when any tracked file does, since editing one changes neither.
+    // A tracked file that's been deleted doesn't exist to watch, which makes
+    // cargo rerun every time: right, since the tree stays dirty until it's
+    // restored or the deletion is committed.
+    if let Some(files) = git(&["ls-files", "-z"]) {
+        for path in files.split('\0').filter(|path| !path.is_empty()) {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
@@ -0,0 +1,91 @@
+//! What the global allocator can tell us about memory use
+//!
+//! The allocator is picked by the `jemalloc` or `mimalloc` cargo feature,
+//! and installed here as the global allocator of whatever links the
+//! library: the binary, the tests, or an embedder that turns the feature on
+//! (which then can't install one of its own). With both features jemalloc
+//! wins, so that `--all-features` still builds. Without either it's the
+//! system allocator, which doesn't report anything.
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+/// Name of the allocator the server was built for, like Redis' `mem_allocator`
+pub const NAME: &str = if cfg!(feature = "jemalloc") {
+    "jemalloc"
+} else if cfg!(feature = "mimalloc") {
+    "mimalloc"
+} else {
+    "libc"
+};
+
+/// Memory as the allocator sees it
+pub struct AllocatorStats {
+    /// Bytes handed out to the program and not yet freed
+    pub allocated: usize,
+    /// Bytes of physical memory the allocator is holding on to
+    pub resident: usize,
+}
+
+impl AllocatorStats {
+    /// How much more memory is resident than allocated, the equivalent of
+    /// Redis' `allocator_frag_ratio`
+    pub fn fragmentation_ratio(&self) -> f64 {
+        if self.allocated == 0 {
+            return 0.0;
+        }
+        self.resident as f64 / self.allocated as f64
+    }
+}
+
+/// Current allocator counters, if the allocator keeps any
+#[cfg(feature = "jemalloc")]
+pub fn stats() -> Option<AllocatorStats> {
+    use tikv_jemalloc_ctl::{epoch, stats};
+
+    // jemalloc only refreshes its statistics when the epoch is bumped
+    epoch::advance().ok()?;
+    Some(AllocatorStats {
+        allocated: stats::allocated::read().ok()?,
+        resident: stats::resident::read().ok()?,
+    })
+}
+
+/// Current allocator counters, if the allocator keeps any
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+pub fn stats() -> Option<AllocatorStats> {
+    let (mut elapsed, mut user, mut system) = (0, 0, 0);
+    let (mut rss, mut peak_rss, mut commit, mut peak_commit, mut faults) = (0, 0, 0, 0, 0);
+
+    // SAFETY: every pointer refers to a live local for the whole call
+    unsafe {
+        libmimalloc_sys::mi_process_info(
+            &mut elapsed,
+            &mut user,
+            &mut system,
+            &mut rss,
+            &mut peak_rss,
+            &mut commit,
+            &mut peak_commit,
+            &mut faults,
+        );
+    }
+
+    // mimalloc only tracks committed memory, which is the closest it has to
+    // an allocated byte count
+    Some(AllocatorStats {
+        allocated: commit,
+        resident: rss,
+    })
+}
+
+/// Current allocator counters, if the allocator keeps any
+#[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+pub fn stats() -> Option<AllocatorStats> {
+    None
+}
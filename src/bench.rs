@@ -0,0 +1,541 @@
+//! `rust-redis-server bench`: a small `redis-benchmark` work-alike
+//!
+//! Each test opens `clients` connections which share a budget of `requests`
+//! commands between them, sending `pipeline` commands at a time and timing
+//! how long each batch takes to come back.
+
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::protocol::{self, Frame};
+use anyhow::{Result, bail};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// What `--help` shows
+pub const USAGE: &str = "\
+Usage: rust-redis-server bench [OPTIONS]
+
+  -h <hostname>   Server hostname (default 127.0.0.1)
+  -p <port>       Server port (default 6379)
+  -c <clients>    Number of parallel connections (default 50)
+  -n <requests>   Total number of requests per test (default 100000)
+  -P <numreq>     Pipeline <numreq> requests (default 1, no pipelining)
+  -d <size>       Data size of SET/LPUSH/RPUSH values in bytes (default 3)
+  -r <keyspace>   Use random keys for SET/GET/INCR/LPUSH/RPUSH
+  -t <tests>      Comma separated list of tests (default ping,set,get,incr,lpush)
+  -q              Quiet, just show requests per second and p50 latency
+  --help          Show this help";
+
+/// Which command mix to run and how hard to push it
+pub struct BenchConfig {
+    pub host: String,
+    pub port: u16,
+    pub clients: usize,
+    pub requests: usize,
+    pub pipeline: usize,
+    pub data_size: usize,
+    /// Spread keys over this many values instead of using one fixed key
+    pub keyspace: Option<u64>,
+    pub tests: Vec<Test>,
+    /// Report one line per test instead of the full summary
+    pub quiet: bool,
+}
+
+/// A command to benchmark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Test {
+    Ping,
+    Set,
+    Get,
+    Incr,
+    Lpush,
+    Rpush,
+}
+
+impl BenchConfig {
+    /// Parse `redis-benchmark` style flags
+    ///
+    /// Returns `None` for `--help`, which should show [`USAGE`] instead.
+    pub fn from_args<I>(args: I) -> Result<Option<Self>>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut config = Self {
+            host: "127.0.0.1".to_owned(),
+            port: 6379,
+            clients: 50,
+            requests: 100_000,
+            pipeline: 1,
+            data_size: 3,
+            keyspace: None,
+            tests: vec![Test::Ping, Test::Set, Test::Get, Test::Incr, Test::Lpush],
+            quiet: false,
+        };
+        let mut args = args.into_iter();
+
+        while let Some(flag) = args.next() {
+            if flag == "--help" {
+                return Ok(None);
+            }
+            if flag == "-q" {
+                config.quiet = true;
+                continue;
+            }
+
+            let Some(value) = args.next() else {
+                bail!("missing value for '{}'\n\n{}", flag, USAGE);
+            };
+            match flag.as_str() {
+                "-h" => config.host = value,
+                "-p" => config.port = value.parse()?,
+                "-c" => config.clients = value.parse()?,
+                "-n" => config.requests = value.parse()?,
+                "-P" => config.pipeline = value.parse()?,
+                "-d" => config.data_size = value.parse()?,
+                "-r" => config.keyspace = Some(value.parse()?),
+                "-t" => {
+                    config.tests = value
+                        .split(',')
+                        .map(|name| name.trim().parse())
+                        .collect::<Result<_>>()?;
+                }
+                _ => bail!("unknown option '{}'\n\n{}", flag, USAGE),
+            }
+        }
+
+        if config.clients == 0 || config.pipeline == 0 {
+            bail!("-c and -P must be at least 1");
+        }
+        Ok(Some(config))
+    }
+}
+
+/// Run every configured test in turn and print a report for each
+pub async fn run(config: BenchConfig) -> Result<()> {
+    let config = Arc::new(config);
+
+    for &test in &config.tests {
+        let report = run_test(&config, test).await?;
+        print!("{}", report.render(&config, test));
+    }
+    Ok(())
+}
+
+/// Outcome of a single test
+pub struct Report {
+    pub elapsed: Duration,
+    /// Per-request latencies
+    pub latencies: Histogram,
+    /// How many replies were errors
+    pub errors: usize,
+}
+
+/// Run one test against the configured server
+pub async fn run_test(config: &Arc<BenchConfig>, test: Test) -> Result<Report> {
+    let remaining = Arc::new(AtomicUsize::new(config.requests));
+
+    // Connecting isn't part of what's being measured, so every client is
+    // connected before the clock starts
+    let mut sockets = Vec::with_capacity(config.clients);
+    for _ in 0..config.clients {
+        let socket = TcpStream::connect((config.host.as_str(), config.port)).await?;
+        socket.set_nodelay(true)?;
+        sockets.push(socket);
+    }
+
+    let start = Instant::now();
+    let mut workers = Vec::with_capacity(config.clients);
+    for (id, socket) in sockets.into_iter().enumerate() {
+        let config = Arc::clone(config);
+        let remaining = Arc::clone(&remaining);
+        workers.push(tokio::spawn(async move {
+            worker(socket, &config, test, &remaining, id as u64).await
+        }));
+    }
+
+    let mut report = Report {
+        elapsed: Duration::ZERO,
+        latencies: Histogram::default(),
+        errors: 0,
+    };
+    for worker in workers {
+        let (latencies, errors) = worker.await??;
+        report.latencies.merge(&latencies);
+        report.errors += errors;
+    }
+    report.elapsed = start.elapsed();
+
+    Ok(report)
+}
+
+/// One client connection: claim a batch, send it, wait for every reply
+async fn worker(
+    mut socket: TcpStream,
+    config: &BenchConfig,
+    test: Test,
+    remaining: &AtomicUsize,
+    id: u64,
+) -> Result<(Histogram, usize)> {
+    let mut rng = XorShift::new(id);
+    let mut latencies = Histogram::default();
+    let mut errors = 0;
+    let mut request = Vec::new();
+    let mut replies = Vec::with_capacity(16 * 1024);
+    let mut chunk = vec![0u8; 16 * 1024];
+    let value = vec![b'x'; config.data_size];
+
+    loop {
+        let batch = claim(remaining, config.pipeline);
+        if batch == 0 {
+            break;
+        }
+
+        request.clear();
+        for _ in 0..batch {
+            let key = match config.keyspace {
+                Some(keyspace) => format!("{:012}", rng.next() % keyspace.max(1)),
+                None => "__rand_int__".to_owned(),
+            };
+            test.encode(&key, &value, &mut request);
+        }
+
+        let sent = Instant::now();
+        socket.write_all(&request).await?;
+
+        let mut received = 0;
+        while received < batch {
+            match protocol::parse_frame(&replies)? {
+                Some((frame, len)) => {
+                    replies.drain(..len);
+                    received += 1;
+                    if matches!(frame, Frame::Error(_)) {
+                        errors += 1;
+                    }
+                }
+                None => {
+                    let n = socket.read(&mut chunk).await?;
+                    if n == 0 {
+                        bail!("server closed the connection");
+                    }
+                    replies.extend_from_slice(&chunk[..n]);
+                }
+            }
+        }
+
+        // Every request in a pipeline is answered at (roughly) the same time,
+        // so they all get the batch's round trip as their latency
+        let latency = sent.elapsed();
+        latencies.record_n(latency, batch as u64);
+    }
+
+    Ok((latencies, errors))
+}
+
+/// Take up to `want` requests from the shared budget
+fn claim(remaining: &AtomicUsize, want: usize) -> usize {
+    let mut claimed = 0;
+    let _ = remaining.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+        claimed = left.min(want);
+        Some(left - claimed)
+    });
+    claimed
+}
+
+impl Test {
+    /// The name the report uses, which is the command's
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ping => "PING",
+            Self::Set => "SET",
+            Self::Get => "GET",
+            Self::Incr => "INCR",
+            Self::Lpush => "LPUSH",
+            Self::Rpush => "RPUSH",
+        }
+    }
+
+    fn encode(self, key: &str, value: &[u8], out: &mut Vec<u8>) {
+        match self {
+            Self::Ping => protocol::encode_command(&[b"PING".as_slice()], out),
+            Self::Set => protocol::encode_command(
+                &[b"SET".as_slice(), format!("key:{}", key).as_bytes(), value],
+                out,
+            ),
+            Self::Get => protocol::encode_command(
+                &[b"GET".as_slice(), format!("key:{}", key).as_bytes()],
+                out,
+            ),
+            Self::Incr => protocol::encode_command(
+                &[b"INCR".as_slice(), format!("counter:{}", key).as_bytes()],
+                out,
+            ),
+            Self::Lpush => protocol::encode_command(
+                &[
+                    b"LPUSH".as_slice(),
+                    format!("mylist:{}", key).as_bytes(),
+                    value,
+                ],
+                out,
+            ),
+            Self::Rpush => protocol::encode_command(
+                &[
+                    b"RPUSH".as_slice(),
+                    format!("mylist:{}", key).as_bytes(),
+                    value,
+                ],
+                out,
+            ),
+        }
+    }
+}
+
+impl std::str::FromStr for Test {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "ping" => Self::Ping,
+            "set" => Self::Set,
+            "get" => Self::Get,
+            "incr" => Self::Incr,
+            "lpush" => Self::Lpush,
+            "rpush" => Self::Rpush,
+            _ => bail!(
+                "unknown test '{}', expected ping, set, get, incr, lpush or rpush",
+                s
+            ),
+        })
+    }
+}
+
+impl Report {
+    /// Format the report the way `redis-benchmark` prints it
+    pub fn render(&self, config: &BenchConfig, test: Test) -> String {
+        let mut out = String::new();
+        self.write(&mut out, config, test)
+            .expect("writing to a String can't fail");
+        out
+    }
+
+    fn write(&self, out: &mut impl fmt::Write, config: &BenchConfig, test: Test) -> fmt::Result {
+        let secs = self.elapsed.as_secs_f64();
+        let rps = if secs > 0.0 {
+            self.latencies.count() as f64 / secs
+        } else {
+            0.0
+        };
+
+        if config.quiet {
+            return writeln!(
+                out,
+                "{}: {:.2} requests per second, p50={:.3} msec",
+                test.name(),
+                rps,
+                millis(self.latencies.percentile(50.0))
+            );
+        }
+
+        writeln!(out, "====== {} ======", test.name())?;
+        writeln!(
+            out,
+            "  {} requests completed in {:.2} seconds",
+            self.latencies.count(),
+            secs
+        )?;
+        writeln!(out, "  {} parallel clients", config.clients)?;
+        writeln!(out, "  {} bytes payload", config.data_size)?;
+        if config.pipeline > 1 {
+            writeln!(out, "  pipeline of {} requests", config.pipeline)?;
+        }
+        if self.errors > 0 {
+            writeln!(out, "  {} error replies", self.errors)?;
+        }
+        writeln!(out)?;
+        writeln!(out, "Summary:")?;
+        writeln!(out, "  throughput summary: {:.2} requests per second", rps)?;
+        writeln!(out, "  latency summary (msec):")?;
+        writeln!(
+            out,
+            "  {:>9} {:>9} {:>9} {:>9} {:>9} {:>9}",
+            "avg", "min", "p50", "p95", "p99", "max"
+        )?;
+        writeln!(
+            out,
+            "  {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+            millis(self.latencies.average()),
+            millis(self.latencies.min()),
+            millis(self.latencies.percentile(50.0)),
+            millis(self.latencies.percentile(95.0)),
+            millis(self.latencies.percentile(99.0)),
+            millis(self.latencies.max()),
+        )?;
+        writeln!(out)
+    }
+}
+
+/// Latencies counted in buckets, the way `redis-benchmark` uses an
+/// HdrHistogram: memory depends on the longest latency, not on how many
+/// were recorded
+///
+/// Below [`EXACT_MICROS`] every microsecond has a bucket of its own. Above,
+/// each doubling of the latency is split into [`SUB_BUCKETS`] buckets, so a
+/// bucket is less than 0.4% wide. Percentiles are reported as the lowest
+/// latency of their bucket.
+#[derive(Clone, Default)]
+pub struct Histogram {
+    /// Recorded latencies per bucket, grown as longer latencies come in
+    counts: Vec<u64>,
+    count: u64,
+    total_nanos: u128,
+    min: Duration,
+    max: Duration,
+}
+
+/// Buckets per doubling of the latency, past [`EXACT_MICROS`]
+const SUB_BUCKETS: u64 = 256;
+
+/// Latencies below this many microseconds are recorded exactly
+const EXACT_MICROS: u64 = 2 * SUB_BUCKETS;
+
+/// Longer latencies are recorded as this, about 12 days
+const MAX_MICROS: u64 = (1 << 40) - 1;
+
+impl Histogram {
+    /// Record one latency
+    pub fn record(&mut self, latency: Duration) {
+        self.record_n(latency, 1);
+    }
+
+    /// Record the same latency `n` times
+    pub fn record_n(&mut self, latency: Duration, n: u64) {
+        if n == 0 {
+            return;
+        }
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        let bucket = bucket(micros.min(MAX_MICROS));
+        if self.counts.len() <= bucket {
+            self.counts.resize(bucket + 1, 0);
+        }
+        self.counts[bucket] += n;
+
+        if self.count == 0 || latency < self.min {
+            self.min = latency;
+        }
+        self.max = self.max.max(latency);
+        self.count += n;
+        self.total_nanos += latency.as_nanos() * u128::from(n);
+    }
+
+    /// Add everything `other` recorded
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.count == 0 {
+            return;
+        }
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+
+        if self.count == 0 || other.min < self.min {
+            self.min = other.min;
+        }
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+        self.total_nanos += other.total_nanos;
+    }
+
+    /// How many latencies were recorded
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Nearest-rank percentile, zero if nothing was recorded
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((p / 100.0 * self.count as f64).ceil() as u64).clamp(1, self.count);
+        if rank == self.count {
+            return self.max;
+        }
+
+        let mut seen = 0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let latency = Duration::from_micros(lowest_in_bucket(bucket));
+                return latency.clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Mean latency, zero if nothing was recorded
+    pub fn average(&self) -> Duration {
+        match self.total_nanos.checked_div(u128::from(self.count)) {
+            Some(nanos) => Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX)),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Shortest latency, exactly
+    pub fn min(&self) -> Duration {
+        self.min
+    }
+
+    /// Longest latency, exactly
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+}
+
+/// Which bucket a latency of `micros` goes in
+fn bucket(micros: u64) -> usize {
+    // Past the exact range, shift `micros` down into [SUB_BUCKETS,
+    // EXACT_MICROS); each doubling takes one more shift and SUB_BUCKETS
+    // more buckets
+    let shift = (u64::BITS - micros.leading_zeros()).saturating_sub(EXACT_MICROS.ilog2());
+    (u64::from(shift) * SUB_BUCKETS + (micros >> shift)) as usize
+}
+
+/// The shortest latency in `bucket`, in microseconds
+fn lowest_in_bucket(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < EXACT_MICROS {
+        return bucket;
+    }
+    let shift = bucket / SUB_BUCKETS - 1;
+    (bucket - shift * SUB_BUCKETS) << shift
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Tiny xorshift PRNG; key choice only needs to be spread out, not secure
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // Zero is a fixed point for xorshift, so never start there
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
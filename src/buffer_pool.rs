@@ -0,0 +1,123 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+/// A pool of reusable connection buffers
+///
+/// Short-lived connections would otherwise allocate (and free) a fresh
+/// multi-kilobyte buffer every time they connect. Instead, buffers are checked
+/// out of the pool and handed back when the connection is done with them.
+///
+/// * Buffers that grew past `buffer_size` while checked out are dropped rather
+///   than returned, so one large request can't pin memory in the pool forever.
+/// * At most `max_pooled` idle buffers are kept around; extras are dropped.
+pub struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    buffer_size: usize,
+    max_pooled: usize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+/// Snapshot of the pool counters
+pub struct BufferPoolStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub idle: usize,
+}
+
+/// A buffer checked out of a [`BufferPool`], returned to it on drop
+pub struct PooledBuffer {
+    buf: Vec<u8>,
+    pool: Arc<BufferPool>,
+}
+
+impl BufferPool {
+    /// Create an empty pool handing out buffers of `buffer_size` bytes
+    pub fn new(buffer_size: usize, max_pooled: usize) -> Arc<Self> {
+        Arc::new(Self {
+            buffers: Mutex::new(Vec::with_capacity(max_pooled)),
+            buffer_size,
+            max_pooled,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        })
+    }
+
+    /// Check out a buffer, reusing an idle one if there is any
+    pub fn acquire(self: &Arc<Self>) -> PooledBuffer {
+        let reused = self.buffers.lock().unwrap().pop();
+        let buf = match reused {
+            Some(buf) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                buf
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Vec::with_capacity(self.buffer_size)
+            }
+        };
+
+        PooledBuffer {
+            buf,
+            pool: Arc::clone(self),
+        }
+    }
+
+    /// Current hit/miss counters and number of idle buffers
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            idle: self.buffers.lock().unwrap().len(),
+        }
+    }
+
+    fn release(&self, mut buf: Vec<u8>) {
+        if buf.capacity() > self.buffer_size {
+            return;
+        }
+        buf.clear();
+
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.max_pooled {
+            buffers.push(buf);
+        }
+    }
+}
+
+impl BufferPoolStats {
+    /// Fraction of checkouts served from the pool, `0.0` if nothing was checked out yet
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        self.pool.release(std::mem::take(&mut self.buf));
+    }
+}
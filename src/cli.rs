@@ -0,0 +1,342 @@
+//! `rust-redis-server cli`: a small `redis-cli` work-alike
+//!
+//! Runs one command given on the command line, an interactive REPL when there
+//! isn't one, or bulk loads RESP from stdin with `--pipe`. Unlike the server
+//! and `bench`, this is plain blocking I/O: there is only ever one request in
+//! flight (outside of `--pipe`), so there's nothing for a runtime to do.
+
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    protocol::{self, Frame},
+    reply,
+};
+use anyhow::{Context, Result, bail};
+use rustyline::{DefaultEditor, error::ReadlineError};
+
+/// What `--help` shows
+pub const USAGE: &str = "\
+Usage: rust-redis-server cli [OPTIONS] [cmd [arg [arg ...]]]
+
+  -h <hostname>   Server hostname (default 127.0.0.1)
+  -p <port>       Server port (default 6379)
+  --pipe          Transfer raw RESP from stdin to the server
+  --help          Show this help
+
+With no command, starts an interactive prompt.";
+
+/// Where to connect and what to do once connected
+pub struct CliConfig {
+    pub host: String,
+    pub port: u16,
+    /// Bulk load stdin instead of running a command
+    pub pipe: bool,
+    /// The command to run, empty for the REPL
+    pub command: Vec<Vec<u8>>,
+}
+
+impl CliConfig {
+    /// Parse `redis-cli` style flags, stopping at the first non-flag argument
+    ///
+    /// Returns `None` for `--help`, which should show [`USAGE`] instead.
+    pub fn from_args<I>(args: I) -> Result<Option<Self>>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut config = Self {
+            host: "127.0.0.1".to_owned(),
+            port: 6379,
+            pipe: false,
+            command: Vec::new(),
+        };
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--help" => return Ok(None),
+                "--pipe" => config.pipe = true,
+                "-h" | "-p" => {
+                    let Some(value) = args.next() else {
+                        bail!("missing value for '{}'\n\n{}", arg, USAGE);
+                    };
+                    if arg == "-h" {
+                        config.host = value;
+                    } else {
+                        config.port = value.parse()?;
+                    }
+                }
+                _ if arg.starts_with('-') => bail!("unknown option '{}'\n\n{}", arg, USAGE),
+                _ => {
+                    config.command.push(arg.into_bytes());
+                    config.command.extend(args.by_ref().map(String::into_bytes));
+                }
+            }
+        }
+
+        Ok(Some(config))
+    }
+}
+
+/// Run the command, the REPL or `--pipe`, whichever `config` asks for
+pub fn run(config: CliConfig) -> Result<()> {
+    let addr = format!("{}:{}", config.host, config.port);
+
+    if config.pipe {
+        let stats = pipe(&addr, io::stdin())?;
+        println!("All data transferred. Waiting for the last reply...");
+        println!(
+            "Last reply received from server.\nerrors: {}, replies: {}",
+            stats.errors, stats.replies
+        );
+        return Ok(());
+    }
+
+    let mut conn = Connection::open(&addr)?;
+    if !config.command.is_empty() {
+        let reply = conn.call(&config.command)?;
+        print!("{}", render(&reply, 0));
+        return Ok(());
+    }
+
+    repl(&addr, conn)
+}
+
+fn repl(addr: &str, mut conn: Connection) -> Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    let prompt = format!("{}> ", addr);
+
+    loop {
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let args = match protocol::split_args(line.as_bytes()) {
+            Ok(args) if args.is_empty() => continue,
+            Ok(args) => args,
+            Err(e) => {
+                println!("Invalid argument(s): {}", e);
+                continue;
+            }
+        };
+        let _ = editor.add_history_entry(line.as_str());
+
+        if args[0].eq_ignore_ascii_case(b"quit") || args[0].eq_ignore_ascii_case(b"exit") {
+            return Ok(());
+        }
+
+        // The server may have gone away since the last command; try once to
+        // reconnect before giving up on this one
+        let reply = conn.call(&args).or_else(|_| {
+            conn = Connection::open(addr)?;
+            conn.call(&args)
+        });
+        match reply {
+            Ok(reply) => print!("{}", render(&reply, 0)),
+            Err(e) => println!("{:#}", e),
+        }
+    }
+}
+
+/// A blocking RESP connection
+struct Connection {
+    socket: TcpStream,
+    buf: Vec<u8>,
+}
+
+impl Connection {
+    fn open(addr: &str) -> Result<Self> {
+        let socket = TcpStream::connect(addr)
+            .with_context(|| format!("Could not connect to Redis at {}", addr))?;
+
+        Ok(Self {
+            socket,
+            buf: Vec::with_capacity(16 * 1024),
+        })
+    }
+
+    /// Send one command and wait for its reply
+    fn call(&mut self, args: &[Vec<u8>]) -> Result<Frame> {
+        let mut request = Vec::new();
+        protocol::encode_command(args, &mut request);
+        self.socket.write_all(&request)?;
+
+        self.read_reply()
+    }
+
+    fn read_reply(&mut self) -> Result<Frame> {
+        self.read_frame()?.context("Server closed the connection")
+    }
+
+    /// Read the next reply, or `None` if the server hung up instead
+    fn read_frame(&mut self) -> Result<Option<Frame>> {
+        let mut chunk = [0u8; 16 * 1024];
+
+        loop {
+            if let Some((frame, len)) = protocol::parse_frame(&self.buf)? {
+                self.buf.drain(..len);
+                return Ok(Some(frame));
+            }
+
+            let n = self.socket.read(&mut chunk)?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+/// What the server made of a `--pipe` transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipeStats {
+    pub replies: u64,
+    pub errors: u64,
+}
+
+/// Stream `input` to the server as-is, counting replies and errors
+///
+/// Like `redis-cli --pipe`, an `ECHO` with a random marker is sent after the
+/// last byte of input. Its reply must be the last one, which is how we know
+/// everything before it has been answered. A server without `ECHO` answers
+/// with an unknown command error quoting the marker instead, which is just
+/// as good, and isn't counted.
+pub fn pipe(addr: &str, mut input: impl Read + Send + 'static) -> Result<PipeStats> {
+    let mut conn = Connection::open(addr)?;
+    let mut writer = conn.socket.try_clone()?;
+
+    let marker = format!(
+        "{:020}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+            % 10u128.pow(20)
+    );
+
+    let echo = {
+        let mut echo = Vec::new();
+        protocol::encode_command(&["ECHO", &marker], &mut echo);
+        echo
+    };
+    let sender = thread::spawn(move || -> io::Result<()> {
+        io::copy(&mut input, &mut writer)?;
+        writer.write_all(&echo)?;
+        writer.shutdown(Shutdown::Write)
+    });
+
+    let (mut replies, mut errors) = (0u64, 0u64);
+    loop {
+        match conn.read_frame()? {
+            Some(Frame::Bulk(data)) if data == marker.as_bytes() => break,
+            Some(Frame::Error(message)) if message.contains(&marker) => break,
+            Some(Frame::Error(message)) => {
+                errors += 1;
+                eprintln!("{}", message);
+            }
+            Some(_) => {}
+            // Hanging up once it has read everything, marker included, is
+            // as good an answer as any; before that it's a failure
+            None => {
+                if sender.join().expect("input sender panicked").is_err() {
+                    bail!("Server closed the connection");
+                }
+                return Ok(PipeStats { replies, errors });
+            }
+        }
+        replies += 1;
+    }
+
+    sender.join().expect("input sender panicked")?;
+    Ok(PipeStats { replies, errors })
+}
+
+/// Format a reply the way `redis-cli` does in a terminal, with nested
+/// lines indented by `indent` columns
+pub fn render(frame: &Frame, indent: usize) -> String {
+    match frame {
+        Frame::Simple(s) => format!("{}\n", s),
+        Frame::Error(e) => format!("(error) {}\n", e),
+        Frame::Integer(n) => format!("(integer) {}\n", n),
+        Frame::Bulk(data) => format!("{}\n", quote(data)),
+        Frame::Null => "(nil)\n".to_owned(),
+        Frame::Double(value) => format!("(double) {}\n", reply::format_double(*value)),
+        Frame::Boolean(value) => format!("({})\n", value),
+        Frame::BigNumber(digits) => format!("(big number) {}\n", digits),
+        Frame::Verbatim { text, .. } => format!("{}\n", String::from_utf8_lossy(text)),
+        Frame::Attribute { value, .. } => render(value, indent),
+        Frame::Array(items) | Frame::Push(items) => render_items(items, ')', indent),
+        Frame::Set(items) => render_items(items, '~', indent),
+        Frame::Map(pairs) if pairs.is_empty() => "(empty hash)\n".to_owned(),
+        Frame::Map(pairs) => {
+            let width = pairs.len().to_string().len();
+            let mut out = String::new();
+
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(&" ".repeat(indent));
+                }
+                let prefix = format!("{:>width$}# ", i + 1);
+                out.push_str(&prefix);
+                out.push_str(render(key, indent + prefix.len()).trim_end());
+                out.push_str(" => ");
+                out.push_str(&render(value, indent + prefix.len()));
+            }
+            out
+        }
+    }
+}
+
+/// Render an array-like aggregate, each element prefixed with its position
+/// and `marker`
+fn render_items(items: &[Frame], marker: char, indent: usize) -> String {
+    if items.is_empty() {
+        return match marker {
+            '~' => "(empty set)\n",
+            _ => "(empty array)\n",
+        }
+        .to_owned();
+    }
+
+    // Nested elements line up under their parent's first character after
+    // the `N) ` prefix
+    let width = items.len().to_string().len();
+    let mut out = String::new();
+
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(&" ".repeat(indent));
+        }
+        let prefix = format!("{:>width$}{} ", i + 1, marker);
+        out.push_str(&prefix);
+        out.push_str(&render(item, indent + prefix.len()));
+    }
+    out
+}
+
+/// Double-quote a bulk string, escaping anything unprintable
+pub fn quote(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() + 2);
+    out.push('"');
+    for &byte in data {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b' '..=b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,75 @@
+//! Commands added by embedders, registered on the [`ServerBuilder`](crate::ServerBuilder)
+//!
+//! This is the same idea as a Redis module, minus the dynamic loading: an
+//! application that embeds the server implements [`Command`] for each
+//! command it wants to add and registers them before the server starts.
+
+use std::net::SocketAddr;
+
+use crate::reply::ReplyBuilder;
+
+/// A command about to run, or that just ran
+#[derive(Debug, Clone, Copy)]
+pub struct CommandContext<'a> {
+    /// Id of the client that sent the command, as used by [`Server::push`](crate::Server::push)
+    pub client_id: u64,
+    /// Address the client connected from
+    pub addr: SocketAddr,
+    /// The command name followed by its arguments, exactly as sent
+    pub args: &'a [Vec<u8>],
+}
+
+impl CommandContext<'_> {
+    /// The command name, as sent (so not necessarily lowercase)
+    pub fn name(&self) -> &[u8] {
+        &self.args[0]
+    }
+}
+
+/// A command that clients can call
+///
+/// ```
+/// # use redis_server::{command::{Command, CommandContext}, reply::ReplyBuilder};
+/// /// `ECHO message`
+/// struct Echo;
+///
+/// impl Command for Echo {
+///     fn name(&self) -> &str {
+///         "echo"
+///     }
+///
+///     fn arity(&self) -> i32 {
+///         2
+///     }
+///
+///     fn execute(&self, command: &CommandContext<'_>, reply: &mut ReplyBuilder) {
+///         reply.bulk(&command.args[1]);
+///     }
+/// }
+/// ```
+pub trait Command: Send + Sync + 'static {
+    /// What clients call the command, matched case-insensitively
+    fn name(&self) -> &str;
+
+    /// How many arguments the command takes, counting its name, the way
+    /// Redis' command table does: `n` for exactly `n`, `-n` for at least `n`
+    ///
+    /// The server checks it before calling [`Command::execute`].
+    fn arity(&self) -> i32;
+
+    /// Run the command, writing exactly one reply to `reply`
+    ///
+    /// A panic is answered with `-ERR internal error` and disconnects the
+    /// client, not the server.
+    fn execute(&self, command: &CommandContext<'_>, reply: &mut ReplyBuilder);
+}
+
+/// Whether `arity` allows a call with `argc` arguments, name included
+pub(crate) fn arity_allows(arity: i32, argc: usize) -> bool {
+    let required = arity.unsigned_abs() as usize;
+    if arity < 0 {
+        argc >= required
+    } else {
+        argc == required
+    }
+}
@@ -0,0 +1,372 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+
+use crate::protocol::{self, RequestLimits};
+
+/// Server Configuration file
+pub struct ServerConfig {
+    pub ip: String,
+    pub port: u16,
+    pub max_connections: usize,
+    /// Backlog passed to `listen(2)` for connections not yet accepted
+    pub tcp_backlog: u32,
+    /// Capacity of each pooled connection read/write buffer, in bytes
+    pub buffer_size: usize,
+    /// Maximum number of idle buffers kept around for reuse
+    pub buffer_pool_size: usize,
+    /// Longest single argument a client may send, in bytes
+    pub proto_max_bulk_len: usize,
+    /// Most arguments a client may send in one multibulk request
+    pub proto_max_multibulk_len: usize,
+    /// Most bytes of a not-yet-complete request buffered per client
+    pub client_query_buffer_limit: usize,
+    /// New connections accepted per second from any one IP, 0 for no limit
+    pub per_ip_connection_rate: u32,
+    /// Commands executed per second for any one IP before its clients are
+    /// slowed down, 0 for no limit
+    pub per_ip_command_rate: u32,
+    /// Minimum severity that makes it into the log
+    pub log_level: LogLevel,
+    /// Layout of each log line
+    pub log_format: LogFormat,
+    /// Log to this file instead of stdout; reopened on `SIGHUP`
+    pub log_file: Option<PathBuf>,
+    /// Serve Prometheus metrics over HTTP on this port, if set; `Some(0)`
+    /// picks a free one, see [`ServerHandle::metrics_addr`](crate::ServerHandle::metrics_addr)
+    pub metrics_port: Option<u16>,
+    /// Fork into the background on startup (Unix only)
+    pub daemonize: bool,
+    /// Where to write our pid; see [`ServerConfig::pidfile_path`] for the default
+    pub pidfile: Option<PathBuf>,
+    /// Which supervisor to report lifecycle changes to
+    pub supervised: Supervised,
+    /// `rename-command` pairs in the order given; an empty new name
+    /// disables the command
+    pub renamed_commands: Vec<(String, String)>,
+}
+
+/// Process supervisor integration, like Redis' `supervised` option
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Supervised {
+    No,
+    /// Send `sd_notify` READY/STOPPING messages to systemd
+    Systemd,
+    /// Use systemd if `NOTIFY_SOCKET` says we're running under it
+    Auto,
+}
+
+/// Log verbosity, using the same names (and order) as Redis' `loglevel`
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Verbose,
+    Notice,
+    Warning,
+    Nothing,
+}
+
+/// How log lines are laid out
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `pid:role dd Mon yyyy hh:mm:ss.mmm <level> message`, like Redis itself
+    Redis,
+    /// `tracing-subscriber`'s default human readable format
+    Plain,
+    /// One JSON object per line
+    Json,
+}
+
+impl Default for ServerConfig {
+    /// Provide a simple default with a "reasonable" limit on connections
+    fn default() -> Self {
+        Self {
+            ip: "127.0.0.1".to_owned(),
+            port: 6379,
+            max_connections: 100,
+            tcp_backlog: 511,
+            buffer_size: 16 * 1024,
+            buffer_pool_size: 200,
+            proto_max_bulk_len: 512 * 1024 * 1024,
+            proto_max_multibulk_len: 1024 * 1024,
+            client_query_buffer_limit: 1024 * 1024 * 1024,
+            per_ip_connection_rate: 0,
+            per_ip_command_rate: 0,
+            log_level: LogLevel::Notice,
+            log_format: LogFormat::Redis,
+            log_file: None,
+            metrics_port: None,
+            daemonize: false,
+            pidfile: None,
+            supervised: Supervised::No,
+            renamed_commands: Vec::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Build a config from `redis-server` style `--option value` arguments,
+    /// starting from the defaults
+    ///
+    /// As with `redis-server`, an option takes every argument up to the next
+    /// `--option`, so `--rename-command flushall ""` works unquoted.
+    ///
+    /// Every bad argument is reported, along with the [`ServerConfig::problems`]
+    /// of the result, rather than just the first one found.
+    pub fn from_args<I>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut config = Self::default();
+        let mut args = args.into_iter().peekable();
+        let mut problems = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                problems.push(format!(
+                    "unexpected argument '{}', options look like '--port 6379'",
+                    arg
+                ));
+                continue;
+            };
+            let mut words = Vec::new();
+            while let Some(word) = args.next_if(|arg| !arg.starts_with("--")) {
+                words.push(word);
+            }
+            let value = match words.as_slice() {
+                [] => {
+                    problems.push(format!("missing value for option '--{}'", name));
+                    continue;
+                }
+                [value] => value.clone(),
+                // Quoted back into a single value, like a line of a config
+                // file, for the option to split up again
+                _ => words
+                    .iter()
+                    .map(|word| quote(word))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            };
+            if let Err(e) = config.set(name, &value) {
+                problems.push(format!(
+                    "can't apply option '--{} {}': {:#}",
+                    name, value, e
+                ));
+            }
+        }
+
+        problems.extend(config.problems());
+        match problems.as_slice() {
+            [] => Ok(config),
+            [problem] => bail!("{}", problem),
+            _ => bail!(
+                "{} problems with the configuration:\n  - {}",
+                problems.len(),
+                problems.join("\n  - ")
+            ),
+        }
+    }
+
+    /// Options that are fine on their own but don't work together, or
+    /// values no server could run with
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.max_connections == 0 {
+            problems.push("maxclients must be at least 1".to_owned());
+        }
+        if self.buffer_size == 0 {
+            problems.push("buffer-size must be at least 1".to_owned());
+        }
+        // Port 0 picks a different free port for each
+        if self
+            .metrics_port
+            .is_some_and(|port| port == self.port && port != 0)
+        {
+            problems.push(format!(
+                "metrics-port {} is also the client port",
+                self.port
+            ));
+        }
+        if self.client_query_buffer_limit < self.proto_max_bulk_len {
+            problems.push(format!(
+                "client-query-buffer-limit ({}) is below proto-max-bulk-len ({}), \
+                 so the longest allowed argument could never arrive",
+                self.client_query_buffer_limit, self.proto_max_bulk_len
+            ));
+        }
+        if self.daemonize && cfg!(not(unix)) {
+            problems.push("daemonize is only supported on Unix".to_owned());
+        }
+
+        problems
+    }
+
+    /// Set a single option by its config name
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        match name.to_ascii_lowercase().as_str() {
+            "bind" => self.ip = value.to_owned(),
+            "port" => self.port = value.parse()?,
+            "maxclients" => self.max_connections = value.parse()?,
+            "tcp-backlog" => self.tcp_backlog = value.parse()?,
+            "buffer-size" => self.buffer_size = value.parse()?,
+            "buffer-pool-size" => self.buffer_pool_size = value.parse()?,
+            "proto-max-bulk-len" => self.proto_max_bulk_len = parse_memory(value)?,
+            "proto-max-multibulk-len" => self.proto_max_multibulk_len = value.parse()?,
+            "client-query-buffer-limit" => self.client_query_buffer_limit = parse_memory(value)?,
+            "per-ip-connection-rate" => self.per_ip_connection_rate = value.parse()?,
+            "per-ip-command-rate" => self.per_ip_command_rate = value.parse()?,
+            "loglevel" => self.log_level = value.parse()?,
+            "log-format" => self.log_format = value.parse()?,
+            "logfile" => {
+                // Redis treats an empty `logfile` as "log to stdout"
+                self.log_file = (!value.is_empty()).then(|| PathBuf::from(value));
+            }
+            "metrics-port" => {
+                // `0` switches the endpoint off, like `port 0` does for Redis
+                self.metrics_port = Some(value.parse()?).filter(|&port| port != 0);
+            }
+            "daemonize" => self.daemonize = parse_yes_no(value)?,
+            "pidfile" => self.pidfile = (!value.is_empty()).then(|| PathBuf::from(value)),
+            "supervised" => self.supervised = value.parse()?,
+            "rename-command" => self.renamed_commands.push(parse_rename(value)?),
+            _ => bail!("unknown option '{}'", name),
+        }
+        Ok(())
+    }
+
+    /// The pidfile to write, if any
+    ///
+    /// As with Redis, a daemonized server always writes one (defaulting to
+    /// `/var/run/redis.pid`), while a foreground one only does when asked to.
+    pub fn pidfile_path(&self) -> Option<PathBuf> {
+        match &self.pidfile {
+            Some(path) => Some(path.clone()),
+            None if self.daemonize => Some(PathBuf::from("/var/run/redis.pid")),
+            None => None,
+        }
+    }
+
+    /// The request size limits the protocol parser enforces
+    pub fn request_limits(&self) -> RequestLimits {
+        RequestLimits {
+            max_multibulk_len: self.proto_max_multibulk_len,
+            max_bulk_len: self.proto_max_bulk_len,
+        }
+    }
+
+    /// Whether lifecycle changes should be reported to systemd
+    pub fn notify_systemd(&self) -> bool {
+        match self.supervised {
+            Supervised::No => false,
+            Supervised::Systemd => true,
+            Supervised::Auto => std::env::var_os("NOTIFY_SOCKET").is_some(),
+        }
+    }
+}
+
+/// Parse a byte count with an optional Redis memory unit: `k`/`m`/`g` are
+/// powers of 1000, `kb`/`mb`/`gb` powers of 1024
+fn parse_memory(value: &str) -> Result<usize> {
+    let value = value.to_ascii_lowercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+
+    let multiplier: usize = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => bail!("unknown memory unit '{}'", unit),
+    };
+    digits
+        .parse::<usize>()?
+        .checked_mul(multiplier)
+        .context("memory value out of range")
+}
+
+/// Quote `word` so that [`protocol::split_args`] reads it back as is
+fn quote(word: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in word.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\x{:02x}", c as u8)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parse a `rename-command` value, `OLD NEW` with `NEW` quoted when empty
+fn parse_rename(value: &str) -> Result<(String, String)> {
+    let words = protocol::split_args(value.as_bytes())?;
+    let [from, to] = words.as_slice() else {
+        bail!("expected a command name and its new name, e.g. 'flushall \"\"'");
+    };
+    Ok((
+        String::from_utf8_lossy(from).to_lowercase(),
+        String::from_utf8_lossy(to).to_lowercase(),
+    ))
+}
+
+fn parse_yes_no(value: &str) -> Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => bail!("expected yes or no"),
+    }
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "debug" => Self::Debug,
+            "verbose" => Self::Verbose,
+            "notice" => Self::Notice,
+            "warning" => Self::Warning,
+            "nothing" => Self::Nothing,
+            _ => bail!("expected one of debug, verbose, notice, warning, nothing"),
+        })
+    }
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "redis" => Self::Redis,
+            "plain" => Self::Plain,
+            "json" => Self::Json,
+            _ => bail!("expected one of redis, plain, json"),
+        })
+    }
+}
+
+impl std::str::FromStr for Supervised {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "no" => Self::No,
+            "systemd" => Self::Systemd,
+            "auto" => Self::Auto,
+            _ => bail!("expected one of no, systemd, auto"),
+        })
+    }
+}
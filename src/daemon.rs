@@ -0,0 +1,92 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use tracing::{debug, warn};
+
+/// Detach from the terminal and keep running in the background
+///
+/// This forks, so it must run before the Tokio runtime (or anything else that
+/// starts threads) exists: only the forking thread survives in the child.
+#[cfg(unix)]
+pub fn daemonize() -> Result<()> {
+    use std::{fs::OpenOptions, io, os::fd::AsRawFd};
+
+    // SAFETY: `main` calls this before any other thread has been spawned
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error().into()),
+        0 => {}
+        _ => std::process::exit(0),
+    }
+
+    // Become the leader of a new session so we lose the controlling terminal
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    // Nobody is reading our stdio anymore
+    let dev_null = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::dup2(dev_null.as_raw_fd(), fd) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn daemonize() -> Result<()> {
+    anyhow::bail!("daemonize is only supported on Unix")
+}
+
+/// Record our pid so init scripts can find (and signal) us
+///
+/// Like Redis, failing to write the pidfile is only worth a warning.
+pub fn write_pidfile(path: &Path) {
+    if let Err(e) = fs::write(path, format!("{}\n", std::process::id())) {
+        warn!("Failed to write PID file {}: {}", path.display(), e);
+    }
+}
+
+pub fn remove_pidfile(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        warn!("Failed to remove PID file {}: {}", path.display(), e);
+    }
+}
+
+/// Tell systemd about a lifecycle change, e.g. `READY=1` or `STOPPING=1`
+///
+/// This is the `sd_notify(3)` protocol: a datagram of newline-separated
+/// assignments sent to the socket named by `$NOTIFY_SOCKET`. A leading `@`
+/// means a Linux abstract socket rather than a path.
+#[cfg(unix)]
+pub fn sd_notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(socket_path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    let sent = UnixDatagram::unbound().and_then(|socket| {
+        #[cfg(target_os = "linux")]
+        if let Some(name) = socket_path.as_encoded_bytes().strip_prefix(b"@") {
+            use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+            let addr = SocketAddr::from_abstract_name(name)?;
+            return socket.send_to_addr(state.as_bytes(), &addr);
+        }
+
+        socket.send_to(state.as_bytes(), &socket_path)
+    });
+
+    match sent {
+        Ok(_) => debug!("Sent {:?} to systemd", state),
+        Err(e) => warn!("Failed to notify systemd: {}", e),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn sd_notify(_state: &str) {}
@@ -0,0 +1,133 @@
+/// Recursion depth at which a pattern is considered abusive and fails to
+/// match, same as Redis
+const MAX_NESTING: usize = 1000;
+
+/// Glob-style matching with the exact semantics of Redis' `stringmatchlen`,
+/// as used by KEYS, SCAN MATCH, PSUBSCRIBE, CONFIG GET and ACL key patterns
+///
+/// * `*` matches any run of bytes and `?` any single byte
+/// * `[abc]`, `[a-z]` and `[^...]` match one byte from (or not from) a set;
+///   ranges may be given backwards, an unterminated set runs to the end of
+///   the pattern
+/// * `\` makes the next byte literal, both inside and outside sets
+///
+/// Redis' quirks are kept on purpose, since clients rely on them: a lone
+/// `*` doesn't match the empty string, and escapes inside a set ignore
+/// `nocase`.
+pub fn string_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let mut skip_longer_matches = false;
+    match_from(pattern, string, nocase, &mut skip_longer_matches, 0)
+}
+
+fn match_from(
+    mut pattern: &[u8],
+    mut string: &[u8],
+    nocase: bool,
+    skip_longer_matches: &mut bool,
+    nesting: usize,
+) -> bool {
+    if nesting > MAX_NESTING {
+        return false;
+    }
+
+    let eq = |a: u8, b: u8| {
+        if nocase {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    };
+
+    while !pattern.is_empty() && !string.is_empty() {
+        match pattern[0] {
+            b'*' => {
+                while pattern.get(1) == Some(&b'*') {
+                    pattern = &pattern[1..];
+                }
+                if pattern.len() == 1 {
+                    return true;
+                }
+                while !string.is_empty() {
+                    if match_from(
+                        &pattern[1..],
+                        string,
+                        nocase,
+                        skip_longer_matches,
+                        nesting + 1,
+                    ) {
+                        return true;
+                    }
+                    if *skip_longer_matches {
+                        return false;
+                    }
+                    string = &string[1..];
+                }
+
+                // The rest of the pattern can't match anywhere in what's
+                // left of the string. Any earlier `*` trying a longer match
+                // would only leave it less string to work with, so tell
+                // them all to give up
+                *skip_longer_matches = true;
+                return false;
+            }
+            b'?' => string = &string[1..],
+            b'[' => {
+                pattern = &pattern[1..];
+                let negate = pattern.first() == Some(&b'^');
+                if negate {
+                    pattern = &pattern[1..];
+                }
+
+                let c = string[0];
+                let mut matched = false;
+                loop {
+                    match *pattern {
+                        [b'\\', escaped, ..] => {
+                            pattern = &pattern[1..];
+                            matched |= escaped == c;
+                        }
+                        [b']', ..] | [] => break,
+                        [start, b'-', end, ..] => {
+                            let (mut start, mut end) = (start.min(end), start.max(end));
+                            let mut c = c;
+                            if nocase {
+                                start = start.to_ascii_lowercase();
+                                end = end.to_ascii_lowercase();
+                                c = c.to_ascii_lowercase();
+                            }
+                            pattern = &pattern[2..];
+                            matched |= (start..=end).contains(&c);
+                        }
+                        [literal, ..] => matched |= eq(literal, c),
+                    }
+                    pattern = &pattern[1..];
+                }
+
+                if matched == negate {
+                    return false;
+                }
+                string = &string[1..];
+            }
+            _ => {
+                if pattern[0] == b'\\' && pattern.len() >= 2 {
+                    pattern = &pattern[1..];
+                }
+                if !eq(pattern[0], string[0]) {
+                    return false;
+                }
+                string = &string[1..];
+            }
+        }
+
+        // An unterminated set has already used up the whole pattern
+        pattern = pattern.get(1..).unwrap_or_default();
+        if string.is_empty() {
+            while pattern.first() == Some(&b'*') {
+                pattern = &pattern[1..];
+            }
+            break;
+        }
+    }
+
+    pattern.is_empty() && string.is_empty()
+}
@@ -0,0 +1,37 @@
+//! Callbacks for embedders that want to watch commands as they run
+//!
+//! Hooks are registered on the [`ServerBuilder`](crate::ServerBuilder) and
+//! called on the connection's task, in between reading a request and
+//! queueing its reply. That makes them a good fit for audit logs or for
+//! feeding a change stream, but anything slow belongs on a channel to a
+//! task of its own: until a hook returns, the client waits.
+
+pub use crate::command::CommandContext;
+
+/// Called around every command the server executes
+///
+/// Both methods do nothing by default, so implement whichever is needed. A
+/// hook that panics is treated like a command that panics: the client gets
+/// `-ERR internal error` and is disconnected.
+///
+/// ```
+/// # use std::sync::atomic::{AtomicUsize, Ordering};
+/// # use redis_server::hooks::{CommandContext, CommandHook};
+/// #[derive(Default)]
+/// struct CountErrors(AtomicUsize);
+///
+/// impl CommandHook for CountErrors {
+///     fn after(&self, _command: &CommandContext<'_>, reply: &[u8]) {
+///         if reply.starts_with(b"-") {
+///             self.0.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }
+/// }
+/// ```
+pub trait CommandHook: Send + Sync + 'static {
+    /// Called before the command runs
+    fn before(&self, _command: &CommandContext<'_>) {}
+
+    /// Called after the command ran, with its reply encoded for the client
+    fn after(&self, _command: &CommandContext<'_>, _reply: &[u8]) {}
+}
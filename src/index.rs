@@ -0,0 +1,57 @@
+//! Turning the signed, inclusive indexes commands take into slice ranges
+//!
+//! Redis lets clients count from the end with negative indexes (`-1` is the
+//! last element) and clamps out-of-range ends instead of failing. The exact
+//! rules differ a little between commands, so each flavour gets its own
+//! function here rather than every command doing its own arithmetic.
+
+use std::ops::Range;
+
+/// A single element index, as taken by LINDEX and LSET
+///
+/// Negative indexes count from the end. Unlike ranges, nothing is clamped:
+/// an index outside the collection is `None`.
+pub fn element(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 {
+        index.checked_add(len as i64)?
+    } else {
+        index
+    };
+    usize::try_from(index).ok().filter(|&index| index < len)
+}
+
+/// An inclusive `start`..=`end` rank range, as taken by LRANGE, LTRIM and
+/// ZRANGE (by rank)
+///
+/// Negative ends count from the end and the range is clamped to the
+/// collection. A range that ends up empty is returned as `0..0`.
+pub fn ranks(start: i64, end: i64, len: usize) -> Range<usize> {
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end };
+
+    if start > end || start >= len {
+        return 0..0;
+    }
+    start as usize..end.min(len - 1) as usize + 1
+}
+
+/// An inclusive `start`..=`end` byte range, as taken by GETRANGE
+///
+/// It's like [`ranks`], except that an `end` before the first byte is
+/// clamped to it rather than making the range empty. The one exception, as
+/// in Redis, is when both ends are negative and the wrong way round.
+pub fn bytes(start: i64, end: i64, len: usize) -> Range<usize> {
+    if start < 0 && end < 0 && start > end {
+        return 0..0;
+    }
+
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.max(0).min(len - 1);
+
+    if start > end || len == 0 {
+        return 0..0;
+    }
+    start as usize..end as usize + 1
+}
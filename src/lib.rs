@@ -0,0 +1,29 @@
+//! A Redis-compatible server, usable both as the `rust-redis-server` binary
+//! and embedded in other programs.
+//!
+//! Embedding is mostly useful for tests: start a server on an ephemeral port,
+//! point a client at [`ServerHandle::local_addr`], and shut it down when done.
+
+pub mod allocator;
+pub mod bench;
+pub mod buffer_pool;
+pub mod cli;
+pub mod command;
+pub mod config;
+pub mod daemon;
+pub mod glob;
+pub mod hooks;
+pub mod index;
+pub mod logging;
+pub mod metrics;
+pub mod protocol;
+pub mod rate_limit;
+pub mod reply;
+pub mod routing;
+pub mod server;
+pub mod stats;
+pub mod supervisor;
+pub mod version;
+
+pub use config::ServerConfig;
+pub use server::{Server, ServerBuilder, ServerHandle};
@@ -0,0 +1,254 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Result, anyhow};
+use tracing::{Event, Level, Subscriber, level_filters::LevelFilter};
+use tracing_subscriber::{
+    fmt::{FmtContext, FormatEvent, FormatFields, MakeWriter, format::Writer},
+    registry::LookupSpan,
+};
+
+use crate::config::{LogFormat, LogLevel, ServerConfig};
+
+/// Install the global `tracing` subscriber described by the config
+///
+/// When logging to a file, a `SIGHUP` listener is also spawned that reopens the
+/// file, so external tools like `logrotate` can move the old one out of the
+/// way. This needs to be called from inside the Tokio runtime.
+pub fn init(config: &ServerConfig) -> Result<()> {
+    let writer = match &config.log_file {
+        Some(path) => LogWriter::file(path)?,
+        None => LogWriter::stdout(),
+    };
+    let ansi = config.log_file.is_none();
+    let level = level_filter(config.log_level);
+
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(writer.clone());
+    let installed = match config.log_format {
+        LogFormat::Redis => builder.event_format(RedisFormat).try_init(),
+        LogFormat::Plain => builder.with_ansi(ansi).try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+    installed.map_err(|e| anyhow!(e))?;
+
+    #[cfg(unix)]
+    if config.log_file.is_some() {
+        tokio::spawn(reopen_on_sighup(writer));
+    }
+
+    Ok(())
+}
+
+/// Map Redis' `loglevel` names onto `tracing` levels
+fn level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Debug => LevelFilter::TRACE,
+        LogLevel::Verbose => LevelFilter::DEBUG,
+        LogLevel::Notice => LevelFilter::INFO,
+        LogLevel::Warning => LevelFilter::WARN,
+        LogLevel::Nothing => LevelFilter::OFF,
+    }
+}
+
+#[cfg(unix)]
+async fn reopen_on_sighup(writer: LogWriter) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            tracing::warn!(
+                "Unable to listen for SIGHUP, logfile won't be reopened: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        match writer.reopen() {
+            Ok(()) => tracing::info!("Received SIGHUP, logfile reopened"),
+            Err(e) => tracing::warn!("Received SIGHUP, failed to reopen logfile: {}", e),
+        }
+    }
+}
+
+/// Destination for log lines: stdout, or a file that can be swapped out
+#[derive(Clone)]
+struct LogWriter {
+    file: Option<(PathBuf, Arc<Mutex<File>>)>,
+}
+
+enum LogWriterGuard<'a> {
+    Stdout(io::Stdout),
+    File(MutexGuard<'a, File>),
+}
+
+impl LogWriter {
+    fn stdout() -> Self {
+        Self { file: None }
+    }
+
+    fn file(path: &Path) -> Result<Self> {
+        let file = open_log_file(path)?;
+        Ok(Self {
+            file: Some((path.to_owned(), Arc::new(Mutex::new(file)))),
+        })
+    }
+
+    /// Close and reopen the logfile at the same path
+    fn reopen(&self) -> Result<()> {
+        if let Some((path, file)) = &self.file {
+            let reopened = open_log_file(path)?;
+            *file.lock().unwrap() = reopened;
+        }
+        Ok(())
+    }
+}
+
+fn open_log_file(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| anyhow!("can't open the log file {}: {}", path.display(), e))
+}
+
+impl<'a> MakeWriter<'a> for LogWriter {
+    type Writer = LogWriterGuard<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        match &self.file {
+            Some((_, file)) => LogWriterGuard::File(file.lock().unwrap()),
+            None => LogWriterGuard::Stdout(io::stdout()),
+        }
+    }
+}
+
+impl Write for LogWriterGuard<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Stdout(stdout) => stdout.write(buf),
+            Self::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Stdout(stdout) => stdout.flush(),
+            Self::File(file) => file.flush(),
+        }
+    }
+}
+
+/// Redis' own log line layout, e.g.
+/// `4242:M 14 Oct 2026 09:21:07.114 * Ready to accept connections`
+///
+/// Timestamps are in UTC, since there is no timezone database to consult.
+struct RedisFormat;
+
+impl<S, N> FormatEvent<S, N> for RedisFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let level = match *event.metadata().level() {
+            Level::TRACE => '.',
+            Level::DEBUG => '-',
+            Level::INFO => '*',
+            Level::WARN | Level::ERROR => '#',
+        };
+
+        // Role is always `M` until there is replication to be a replica of
+        write!(
+            writer,
+            "{}:M {} {} ",
+            std::process::id(),
+            Timestamp::now(),
+            level
+        )?;
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Wall-clock time split into calendar fields, formatted as `dd Mon yyyy hh:mm:ss.mmm`
+struct Timestamp {
+    year: i64,
+    month: usize,
+    day: u32,
+    secs_of_day: u64,
+    millis: u32,
+}
+
+impl Timestamp {
+    fn now() -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let secs = since_epoch.as_secs();
+        let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+
+        Self {
+            year,
+            month,
+            day,
+            secs_of_day: secs % 86_400,
+            millis: since_epoch.subsec_millis(),
+        }
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+
+        write!(
+            f,
+            "{:02} {} {} {:02}:{:02}:{:02}.{:03}",
+            self.day,
+            MONTHS[self.month - 1],
+            self.year,
+            self.secs_of_day / 3600,
+            self.secs_of_day / 60 % 60,
+            self.secs_of_day % 60,
+            self.millis
+        )
+    }
+}
+
+/// Convert days since the Unix epoch into a `(year, month, day)` civil date
+///
+/// This is Howard Hinnant's
+/// [`civil_from_days`](https://howardhinnant.github.io/date_algorithms.html#civil_from_days),
+/// which works in 400-year "eras" starting on March 1st so leap days fall at
+/// the end of each year.
+fn civil_from_days(days: i64) -> (i64, usize, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as usize;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
@@ -1,12 +1,72 @@
-mod server;
-
-use server::{Server, ServerConfig};
+use redis_server::{
+    Server, ServerConfig, bench, cli, config::Supervised, daemon, logging, version,
+};
 
 use anyhow::Result;
+use tracing::{info, warn};
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1).peekable();
+
+    match args.peek().map(String::as_str) {
+        Some("bench") => {
+            let Some(config) = bench::BenchConfig::from_args(args.skip(1))? else {
+                println!("{}", bench::USAGE);
+                return Ok(());
+            };
+            return tokio::runtime::Runtime::new()?.block_on(bench::run(config));
+        }
+        Some("cli") => {
+            let Some(config) = cli::CliConfig::from_args(args.skip(1))? else {
+                println!("{}", cli::USAGE);
+                return Ok(());
+            };
+            return cli::run(config);
+        }
+        Some("-v" | "--version") => {
+            println!("{}", version::describe());
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let config = ServerConfig::from_args(args)?;
+
+    // Forking has to happen while we're still single threaded, so before the
+    // runtime and its worker threads exist
+    if config.daemonize {
+        daemon::daemonize()?;
+    }
+
+    tokio::runtime::Runtime::new()?.block_on(run(config))
+}
+
+async fn run(config: ServerConfig) -> Result<()> {
+    logging::init(&config)?;
+    info!("oO0OoO0OoO0Oo Redis is starting oO0OoO0OoO0Oo");
+    info!(
+        "Redis version={}, bits={}, commit={}, modified={}, pid={}, just started",
+        version::VERSION,
+        usize::BITS,
+        version::GIT_SHA1,
+        u8::from(version::GIT_DIRTY),
+        std::process::id()
+    );
+
+    if config.supervised == Supervised::Systemd && std::env::var_os("NOTIFY_SOCKET").is_none() {
+        warn!("systemd supervision requested, but NOTIFY_SOCKET not found");
+    }
+
+    let pidfile = config.pidfile_path();
+    if let Some(path) = &pidfile {
+        daemon::write_pidfile(path);
+    }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let config = ServerConfig::default();
     let server = Server::new(config);
-    server.run().await
+    let result = server.run().await;
+
+    if let Some(path) = &pidfile {
+        daemon::remove_pidfile(path);
+    }
+    result
 }
@@ -0,0 +1,195 @@
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, warn};
+
+use crate::{
+    server::{ACCEPT_BACKOFF, Server},
+    stats::ServerStats,
+};
+
+/// How long a scraper gets to send its request and read the response
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Requests bigger than this are not something a Prometheus scraper would send
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+
+/// Serve `GET /metrics` in the Prometheus text exposition format
+///
+/// This is a deliberately tiny HTTP/1.x responder: every request gets one
+/// response and the connection is closed, which is all a scraper needs.
+pub async fn serve(listener: Arc<TcpListener>, server: Arc<Server>) {
+    loop {
+        match listener.accept().await {
+            Ok((socket, addr)) => {
+                debug!("Metrics request from {}", addr);
+                let server = Arc::clone(&server);
+                tokio::spawn(async move {
+                    match tokio::time::timeout(REQUEST_TIMEOUT, respond(socket, &server)).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => debug!("Metrics request from {} failed: {}", addr, e),
+                        Err(_) => debug!("Metrics request from {} timed out", addr),
+                    }
+                });
+            }
+            Err(e) => {
+                // Same as the client listener: whatever went wrong (most
+                // likely running out of file descriptors) won't have fixed
+                // itself on the next try
+                warn!("Metrics listener failed to accept: {}", e);
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+            }
+        }
+    }
+}
+
+async fn respond(mut socket: TcpStream, server: &Server) -> std::io::Result<()> {
+    let mut request = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+
+    // Only the request line matters, but read the whole head so the client
+    // isn't reset while it's still sending headers
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 || request.len() + n > MAX_REQUEST_LEN {
+            break;
+        }
+        request.extend_from_slice(&chunk[..n]);
+    }
+
+    let request_line = request.split(|&b| b == b'\r').next().unwrap_or_default();
+    let mut parts = request_line.split(|&b| b == b' ');
+    let (method, path) = (parts.next(), parts.next());
+
+    let response = match (method, path) {
+        (Some(b"GET"), Some(b"/metrics")) => http_response(
+            "200 OK",
+            "text/plain; version=0.0.4",
+            &render(&server.stats()),
+        ),
+        (Some(b"GET"), _) => http_response("404 Not Found", "text/plain", "not found\n"),
+        _ => http_response(
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n",
+        ),
+    };
+
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// Render the stats snapshot as Prometheus metric families
+fn render(stats: &ServerStats) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        let _ = writeln!(out, "{} {}", name, value);
+    };
+
+    metric(
+        "redis_connected_clients",
+        "gauge",
+        "Number of client connections currently open",
+        stats.connected_clients as f64,
+    );
+    metric(
+        "redis_connections_received_total",
+        "counter",
+        "Total number of connections accepted by the server",
+        stats.total_connections_received as f64,
+    );
+    metric(
+        "redis_rejected_connections_total",
+        "counter",
+        "Connections rejected because the maxclients limit was reached",
+        stats.rejected_connections as f64,
+    );
+    metric(
+        "redis_overflowed_connections_total",
+        "counter",
+        "Accepts that failed because the server ran out of file descriptors",
+        stats.overflowed_connections as f64,
+    );
+    metric(
+        "redis_throttled_connections_total",
+        "counter",
+        "Connections rejected because their IP exceeded the connection rate limit",
+        stats.throttled_connections as f64,
+    );
+    metric(
+        "redis_internal_errors_total",
+        "counter",
+        "Commands that panicked and were answered with an internal error",
+        stats.internal_errors as f64,
+    );
+    metric(
+        "redis_buffer_pool_hits_total",
+        "counter",
+        "Connection buffers served from the pool",
+        stats.buffer_pool.hits as f64,
+    );
+    metric(
+        "redis_buffer_pool_misses_total",
+        "counter",
+        "Connection buffers that had to be freshly allocated",
+        stats.buffer_pool.misses as f64,
+    );
+    metric(
+        "redis_buffer_pool_idle",
+        "gauge",
+        "Idle buffers currently held by the pool",
+        stats.buffer_pool.idle as f64,
+    );
+    if let Some(allocator) = &stats.allocator {
+        metric(
+            "redis_allocator_allocated_bytes",
+            "gauge",
+            "Bytes allocated by the server and not yet freed",
+            allocator.allocated as f64,
+        );
+        metric(
+            "redis_allocator_resident_bytes",
+            "gauge",
+            "Bytes of physical memory held by the allocator",
+            allocator.resident as f64,
+        );
+        metric(
+            "redis_allocator_fragmentation_ratio",
+            "gauge",
+            "Ratio of resident to allocated bytes",
+            allocator.fragmentation_ratio(),
+        );
+    }
+
+    // One series per task rather than per metric family, so these can't go
+    // through `metric`
+    let _ = writeln!(
+        out,
+        "# HELP redis_task_restarts_total Times a background task was restarted after panicking"
+    );
+    let _ = writeln!(out, "# TYPE redis_task_restarts_total counter");
+    for task in &stats.tasks {
+        let _ = writeln!(
+            out,
+            "redis_task_restarts_total{{task=\"{}\"}} {}",
+            task.name, task.restarts
+        );
+    }
+
+    out
+}
@@ -0,0 +1,541 @@
+//! The Redis serialization protocol (RESP)
+//!
+//! See the [protocol spec](https://redis.io/docs/latest/develop/reference/protocol-spec/).
+//! Every frame starts with a type byte and is terminated by `\r\n`; bulk
+//! strings and arrays carry their length up front, so the parser always knows
+//! whether it has a whole frame yet.
+//!
+//! Clients send commands either as an array of bulk strings (multibulk) or,
+//! for humans typing into `telnet`, as a single whitespace separated line
+//! (inline). [`RequestParser`] and [`parse_request`] accept both.
+
+use std::fmt;
+
+use crate::reply::ReplyBuilder;
+
+/// A single RESP value
+///
+/// Everything after [`Frame::Array`] is RESP3 only; see
+/// [`ReplyBuilder::frame`] for how each is sent to RESP2 clients.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    /// `+OK\r\n`
+    Simple(String),
+    /// `-ERR message\r\n` (or the RESP3 blob error `!`)
+    Error(String),
+    /// `:42\r\n`
+    Integer(i64),
+    /// `$5\r\nhello\r\n`
+    Bulk(Vec<u8>),
+    /// `$-1\r\n` (or the null array `*-1\r\n`, or RESP3's `_\r\n`)
+    Null,
+    /// `*2\r\n...`
+    Array(Vec<Frame>),
+    /// `>2\r\n...`, an out-of-band message rather than a reply
+    Push(Vec<Frame>),
+    /// `%1\r\n...`, key/value pairs in order
+    Map(Vec<(Frame, Frame)>),
+    /// `~2\r\n...`
+    Set(Vec<Frame>),
+    /// `,1.5\r\n`
+    Double(f64),
+    /// `#t\r\n`
+    Boolean(bool),
+    /// `(3492890328409238509324850943850943825024385\r\n`
+    BigNumber(String),
+    /// `=15\r\ntxt:Some string\r\n`
+    Verbatim { format: String, text: Vec<u8> },
+    /// `|1\r\n...` followed by the value the attributes describe
+    Attribute {
+        attributes: Vec<(Frame, Frame)>,
+        value: Box<Frame>,
+    },
+}
+
+/// Which version of the protocol a connection speaks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RespVersion {
+    Resp2,
+    Resp3,
+}
+
+impl Frame {
+    /// Serialize the frame for a client speaking `version`
+    pub fn encode(&self, version: RespVersion, out: &mut Vec<u8>) {
+        ReplyBuilder::new(out, version).frame(self);
+    }
+}
+
+/// Longest line (inline command, or a `*`/`$` length header) we'll buffer
+/// while waiting for its newline, same as Redis' `PROTO_INLINE_MAX_SIZE`
+pub const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Most argument slots reserved up front for a multibulk request, however
+/// many it declares; Redis caps its `argv` preallocation the same way
+const MAX_PREALLOCATED_ARGS: usize = 1024;
+
+/// Input that can never become a valid frame, no matter what follows it
+///
+/// The `Display` text of the request errors matches what Redis puts after
+/// `-ERR Protocol error: `, since some clients and tests look for it.
+#[derive(Debug, PartialEq)]
+pub enum ProtocolError {
+    /// The first byte of a frame isn't a RESP type marker
+    InvalidType(u8),
+    /// A length or integer that isn't a valid number
+    InvalidInteger,
+    /// A RESP3 double or boolean that isn't one
+    InvalidValue(u8),
+    /// Bulk string payload not followed by `\r\n`
+    MissingTerminator,
+    /// `*<count>` that isn't a number or is over the limit
+    InvalidMultibulkLength,
+    /// `$<len>` that isn't a number, is negative, or is over the limit
+    InvalidBulkLength,
+    /// An element of a request array that isn't a bulk string
+    ExpectedBulk(u8),
+    /// An inline command with no newline within [`MAX_LINE_LEN`] bytes
+    TooBigInlineRequest,
+    /// A `*<count>` header with no newline within [`MAX_LINE_LEN`] bytes
+    TooBigMultibulkCount,
+    /// A `$<len>` header with no newline within [`MAX_LINE_LEN`] bytes
+    TooBigBulkCount,
+    /// An inline command (or `redis-cli` line) with an unterminated quote
+    UnbalancedQuotes,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidType(byte) => write!(f, "invalid type byte '{}'", byte.escape_ascii()),
+            Self::InvalidInteger => write!(f, "invalid integer"),
+            Self::InvalidValue(byte) => {
+                write!(f, "invalid value for type '{}'", byte.escape_ascii())
+            }
+            Self::MissingTerminator => write!(f, "expected '\\r\\n' after bulk string"),
+            Self::InvalidMultibulkLength => write!(f, "invalid multibulk length"),
+            Self::InvalidBulkLength => write!(f, "invalid bulk length"),
+            Self::ExpectedBulk(byte) => write!(f, "expected '$', got '{}'", byte.escape_ascii()),
+            Self::TooBigInlineRequest => write!(f, "too big inline request"),
+            Self::TooBigMultibulkCount => write!(f, "too big mbulk count string"),
+            Self::TooBigBulkCount => write!(f, "too big bulk count string"),
+            Self::UnbalancedQuotes => write!(f, "unbalanced quotes in request"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// Encode a command the way clients send them: an array of bulk strings
+pub fn encode_command<A: AsRef<[u8]>>(args: &[A], out: &mut Vec<u8>) {
+    out.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for arg in args {
+        let arg = arg.as_ref();
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Try to parse one frame from the front of `buf`
+///
+/// Returns the frame and how many bytes it took up, or `Ok(None)` if `buf`
+/// doesn't hold a complete frame yet and more needs to be read.
+pub fn parse_frame(buf: &[u8]) -> Result<Option<(Frame, usize)>, ProtocolError> {
+    let mut pos = 0;
+    Ok(parse_at(buf, &mut pos)?.map(|frame| (frame, pos)))
+}
+
+fn parse_at(buf: &[u8], pos: &mut usize) -> Result<Option<Frame>, ProtocolError> {
+    let Some(line) = read_line(buf, pos) else {
+        return Ok(None);
+    };
+    let Some((&kind, rest)) = line.split_first() else {
+        return Err(ProtocolError::InvalidType(b'\r'));
+    };
+
+    let frame = match kind {
+        b'+' => Frame::Simple(String::from_utf8_lossy(rest).into_owned()),
+        b'-' => Frame::Error(String::from_utf8_lossy(rest).into_owned()),
+        b':' => Frame::Integer(parse_int(rest)?),
+        b'$' | b'!' | b'=' => {
+            let len = parse_int(rest)?;
+            if len < 0 {
+                return Ok(Some(Frame::Null));
+            }
+
+            let len = len as usize;
+            let end = *pos + len;
+            if buf.len() < end + 2 {
+                return Ok(None);
+            }
+            if &buf[end..end + 2] != b"\r\n" {
+                return Err(ProtocolError::MissingTerminator);
+            }
+
+            let data = buf[*pos..end].to_vec();
+            *pos = end + 2;
+            match kind {
+                b'!' => Frame::Error(String::from_utf8_lossy(&data).into_owned()),
+                b'=' if data.len() >= 4 && data[3] == b':' => Frame::Verbatim {
+                    format: String::from_utf8_lossy(&data[..3]).into_owned(),
+                    text: data[4..].to_vec(),
+                },
+                _ => Frame::Bulk(data),
+            }
+        }
+        b'_' => Frame::Null,
+        b',' => Frame::Double(parse_double(rest)?),
+        b'#' => match rest {
+            b"t" => Frame::Boolean(true),
+            b"f" => Frame::Boolean(false),
+            _ => return Err(ProtocolError::InvalidValue(kind)),
+        },
+        b'(' => Frame::BigNumber(String::from_utf8_lossy(rest).into_owned()),
+        b'*' | b'>' | b'~' => {
+            let len = parse_int(rest)?;
+            if len < 0 {
+                return Ok(Some(Frame::Null));
+            }
+
+            let mut items = Vec::new();
+            for _ in 0..len {
+                match parse_at(buf, pos)? {
+                    Some(item) => items.push(item),
+                    None => return Ok(None),
+                }
+            }
+            match kind {
+                b'>' => Frame::Push(items),
+                b'~' => Frame::Set(items),
+                _ => Frame::Array(items),
+            }
+        }
+        b'%' | b'|' => {
+            let len = parse_int(rest)?;
+            let mut pairs = Vec::new();
+            for _ in 0..len {
+                let Some(key) = parse_at(buf, pos)? else {
+                    return Ok(None);
+                };
+                let Some(value) = parse_at(buf, pos)? else {
+                    return Ok(None);
+                };
+                pairs.push((key, value));
+            }
+
+            if kind == b'%' {
+                Frame::Map(pairs)
+            } else {
+                let Some(value) = parse_at(buf, pos)? else {
+                    return Ok(None);
+                };
+                Frame::Attribute {
+                    attributes: pairs,
+                    value: Box::new(value),
+                }
+            }
+        }
+        other => return Err(ProtocolError::InvalidType(other)),
+    };
+
+    Ok(Some(frame))
+}
+
+/// A command name followed by its arguments, as sent by a client
+pub type Request = Vec<Vec<u8>>;
+
+/// Upper bounds on what a single request may declare
+#[derive(Clone, Copy)]
+pub struct RequestLimits {
+    /// Most arguments (array elements) in one multibulk request
+    pub max_multibulk_len: usize,
+    /// Longest single argument, in bytes
+    pub max_bulk_len: usize,
+}
+
+/// Try to parse one client request from the front of `buf`
+///
+/// Returns the command's arguments and how many bytes the request took up.
+/// `Ok(None)` means more input is needed, while an empty argument list is a
+/// request that should be skipped (an empty line, or `*0`). Limits are
+/// checked as soon as a length header is seen, so an oversized request is
+/// refused before any of its payload has to be buffered.
+///
+/// This starts over on every call; a connection reading requests as they
+/// arrive should use a [`RequestParser`] instead.
+pub fn parse_request(
+    buf: &[u8],
+    limits: &RequestLimits,
+) -> Result<Option<(Request, usize)>, ProtocolError> {
+    match RequestParser::new(*limits).parse(buf)? {
+        (Some(args), len) => Ok(Some((args, len))),
+        (None, _) => Ok(None),
+    }
+}
+
+/// Parses a connection's requests as its input arrives
+///
+/// Like Redis' `multibulklen` and `bulklen`, what's known about a multibulk
+/// request that hasn't fully arrived is kept between calls, and the bytes
+/// that went into it are consumed. However a request is split up across
+/// reads, each argument is only parsed and copied once.
+pub struct RequestParser {
+    limits: RequestLimits,
+    partial: Option<PartialMultibulk>,
+}
+
+/// A multibulk request that has only partly arrived
+struct PartialMultibulk {
+    /// Arguments still to come, counting the one in progress
+    remaining: usize,
+    args: Request,
+    /// Length of the argument in progress, once its `$` header has been read
+    bulk_len: Option<usize>,
+    /// Bytes of argument data in `args`
+    len: usize,
+}
+
+impl RequestParser {
+    pub fn new(limits: RequestLimits) -> Self {
+        Self {
+            limits,
+            partial: None,
+        }
+    }
+
+    /// Parse as much of the next request as `buf` holds
+    ///
+    /// `buf` is the input that follows whatever earlier calls consumed.
+    /// Returns the request once it's complete, and in any case how many
+    /// bytes from the front of `buf` were consumed, which must not be passed
+    /// in again. As with [`parse_request`], an empty request is one to skip.
+    pub fn parse(&mut self, buf: &[u8]) -> Result<(Option<Request>, usize), ProtocolError> {
+        let mut pos = 0;
+        let mut partial = match self.partial.take() {
+            Some(partial) => partial,
+            None => match buf.first() {
+                None => return Ok((None, 0)),
+                Some(b'*') => match self.parse_count(buf, &mut pos)? {
+                    Some(0) => return Ok((Some(Vec::new()), pos)),
+                    // The count is only a claim until the arguments actually
+                    // arrive, so don't let a lone `*1048576\r\n` allocate
+                    // room for a million of them
+                    Some(count) => PartialMultibulk {
+                        remaining: count,
+                        args: Vec::with_capacity(count.min(MAX_PREALLOCATED_ARGS)),
+                        bulk_len: None,
+                        len: 0,
+                    },
+                    None => return Ok((None, 0)),
+                },
+                Some(_) => {
+                    return Ok(match parse_inline(buf)? {
+                        Some((args, len)) => (Some(args), len),
+                        None => (None, 0),
+                    });
+                }
+            },
+        };
+
+        while partial.remaining > 0 {
+            let len = match partial.bulk_len {
+                Some(len) => len,
+                None => match self.parse_bulk_len(buf, &mut pos)? {
+                    Some(len) => *partial.bulk_len.insert(len),
+                    None => break,
+                },
+            };
+
+            let end = pos + len;
+            if buf.len() < end + 2 {
+                break;
+            }
+            if &buf[end..end + 2] != b"\r\n" {
+                return Err(ProtocolError::MissingTerminator);
+            }
+            partial.args.push(buf[pos..end].to_vec());
+            partial.len += len;
+            partial.bulk_len = None;
+            partial.remaining -= 1;
+            pos = end + 2;
+        }
+
+        if partial.remaining == 0 {
+            return Ok((Some(partial.args), pos));
+        }
+        self.partial = Some(partial);
+        Ok((None, pos))
+    }
+
+    /// Bytes of argument data consumed for a request that isn't complete yet
+    ///
+    /// They no longer take up room in the caller's buffer, but still count
+    /// towards how much input the request is holding on to.
+    pub fn pending_len(&self) -> usize {
+        self.partial.as_ref().map_or(0, |partial| partial.len)
+    }
+
+    /// Read a `*<count>` header, with negative counts meaning no arguments
+    fn parse_count(&self, buf: &[u8], pos: &mut usize) -> Result<Option<usize>, ProtocolError> {
+        let Some(line) = read_line(buf, pos) else {
+            return if buf.len() > MAX_LINE_LEN {
+                Err(ProtocolError::TooBigMultibulkCount)
+            } else {
+                Ok(None)
+            };
+        };
+        let count = parse_int(&line[1..]).map_err(|_| ProtocolError::InvalidMultibulkLength)?;
+        if count > i64::try_from(self.limits.max_multibulk_len).unwrap_or(i64::MAX) {
+            return Err(ProtocolError::InvalidMultibulkLength);
+        }
+        Ok(Some(count.max(0) as usize))
+    }
+
+    /// Read a `$<len>` header at `pos`
+    fn parse_bulk_len(&self, buf: &[u8], pos: &mut usize) -> Result<Option<usize>, ProtocolError> {
+        let Some(&kind) = buf.get(*pos) else {
+            return Ok(None);
+        };
+        if kind != b'$' {
+            return Err(ProtocolError::ExpectedBulk(kind));
+        }
+
+        let header_start = *pos;
+        let Some(line) = read_line(buf, pos) else {
+            return if buf.len() - header_start > MAX_LINE_LEN {
+                Err(ProtocolError::TooBigBulkCount)
+            } else {
+                Ok(None)
+            };
+        };
+        let len = parse_int(&line[1..]).map_err(|_| ProtocolError::InvalidBulkLength)?;
+        if len < 0 || len > i64::try_from(self.limits.max_bulk_len).unwrap_or(i64::MAX) {
+            return Err(ProtocolError::InvalidBulkLength);
+        }
+        Ok(Some(len as usize))
+    }
+}
+
+/// Inline commands end at `\n`, with an optional `\r` before it
+fn parse_inline(buf: &[u8]) -> Result<Option<(Request, usize)>, ProtocolError> {
+    let Some(newline) = buf.iter().position(|&b| b == b'\n') else {
+        return if buf.len() > MAX_LINE_LEN {
+            Err(ProtocolError::TooBigInlineRequest)
+        } else {
+            Ok(None)
+        };
+    };
+
+    let line = &buf[..newline];
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    Ok(Some((split_args(line)?, newline + 1)))
+}
+
+/// Read up to the next `\r\n`, advancing `pos` past it
+fn read_line<'a>(buf: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+    let start = *pos;
+    let len = buf[start..].windows(2).position(|w| w == b"\r\n")?;
+
+    *pos = start + len + 2;
+    Some(&buf[start..start + len])
+}
+
+fn parse_double(digits: &[u8]) -> Result<f64, ProtocolError> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .ok_or(ProtocolError::InvalidValue(b','))
+}
+
+fn parse_int(digits: &[u8]) -> Result<i64, ProtocolError> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or(ProtocolError::InvalidInteger)
+}
+
+/// Split a line into arguments like Redis' `sdssplitargs`
+///
+/// This is how both inline commands and `redis-cli` input are tokenized.
+/// Arguments are separated by whitespace and may be quoted. Double quotes
+/// understand `\n`, `\r`, `\t`, `\a`, `\b`, `\xHH` and escaped quotes; single
+/// quotes only understand `\'`. A closing quote must be followed by
+/// whitespace or the end of the line.
+pub fn split_args(bytes: &[u8]) -> Result<Vec<Vec<u8>>, ProtocolError> {
+    let mut args = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == bytes.len() {
+            return Ok(args);
+        }
+
+        let mut arg = Vec::new();
+        match bytes[i] {
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                loop {
+                    let Some(&byte) = bytes.get(i) else {
+                        return Err(ProtocolError::UnbalancedQuotes);
+                    };
+                    i += 1;
+
+                    match byte {
+                        _ if byte == quote => break,
+                        b'\\' if quote == b'"' => {
+                            let Some(&escaped) = bytes.get(i) else {
+                                return Err(ProtocolError::UnbalancedQuotes);
+                            };
+                            i += 1;
+                            match escaped {
+                                b'n' => arg.push(b'\n'),
+                                b'r' => arg.push(b'\r'),
+                                b't' => arg.push(b'\t'),
+                                b'a' => arg.push(0x07),
+                                b'b' => arg.push(0x08),
+                                b'x' => {
+                                    // from_str_radix would also take a sign
+                                    let hex = bytes
+                                        .get(i..i + 2)
+                                        .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                                        .and_then(|hex| {
+                                            u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16)
+                                                .ok()
+                                        });
+                                    match hex {
+                                        Some(value) => {
+                                            arg.push(value);
+                                            i += 2;
+                                        }
+                                        None => arg.push(b'x'),
+                                    }
+                                }
+                                other => arg.push(other),
+                            }
+                        }
+                        b'\\' if bytes.get(i) == Some(&b'\'') => {
+                            arg.push(b'\'');
+                            i += 1;
+                        }
+                        _ => arg.push(byte),
+                    }
+                }
+
+                if bytes.get(i).is_some_and(|b| !b.is_ascii_whitespace()) {
+                    return Err(ProtocolError::UnbalancedQuotes);
+                }
+            }
+            _ => {
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() {
+                    arg.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        args.push(arg);
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Forget about idle clients once we're tracking this many addresses
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// How often to look for idle clients to forget, at most
+const PRUNE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Token bucket rate limits keyed by client IP
+///
+/// Each address gets a bucket holding up to one second's worth of tokens,
+/// refilled continuously at `rate` per second. A rate of 0 turns the limit
+/// off.
+pub struct IpRateLimiter {
+    rate: u32,
+    state: Mutex<State>,
+}
+
+struct State {
+    buckets: HashMap<IpAddr, Bucket>,
+    pruned: Instant,
+}
+
+struct Bucket {
+    /// Negative while tokens are on loan, see [`IpRateLimiter::reserve`]
+    tokens: f64,
+    updated: Instant,
+}
+
+impl IpRateLimiter {
+    pub fn new(rate: u32) -> Self {
+        Self {
+            rate,
+            state: Mutex::new(State {
+                buckets: HashMap::new(),
+                pruned: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take a token for `ip` if there is one
+    ///
+    /// For limits that are enforced by turning the client away.
+    pub fn try_acquire(&self, ip: IpAddr) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+        self.update(ip, |tokens| {
+            if *tokens >= 1.0 {
+                *tokens -= 1.0;
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Take a token for `ip`, borrowing it if there isn't one, and return
+    /// how long to wait before using it
+    ///
+    /// For limits that are enforced by slowing the client down. A borrowed
+    /// token is paid back from the refill, so clients waiting on the same IP
+    /// get their turns in the order they asked.
+    pub fn reserve(&self, ip: IpAddr) -> Duration {
+        if self.rate == 0 {
+            return Duration::ZERO;
+        }
+        let rate = f64::from(self.rate);
+        self.update(ip, |tokens| {
+            *tokens -= 1.0;
+            Duration::from_secs_f64((-*tokens).max(0.0) / rate)
+        })
+    }
+
+    fn update<T>(&self, ip: IpAddr, f: impl FnOnce(&mut f64) -> T) -> T {
+        let rate = f64::from(self.rate);
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        // Scanning every bucket is only worth it once there are a lot of
+        // them, and then only every so often
+        if state.buckets.len() >= PRUNE_THRESHOLD
+            && now.duration_since(state.pruned) >= PRUNE_INTERVAL
+        {
+            // A full bucket is the same as no bucket at all
+            state
+                .buckets
+                .retain(|_, bucket| bucket.refill(now, rate) < rate);
+            state.pruned = now;
+        }
+
+        let bucket = state.buckets.entry(ip).or_insert(Bucket {
+            tokens: rate,
+            updated: now,
+        });
+        bucket.refill(now, rate);
+        f(&mut bucket.tokens)
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant, rate: f64) -> f64 {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.updated = now;
+        self.tokens
+    }
+}
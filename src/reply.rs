@@ -0,0 +1,247 @@
+use crate::protocol::{Frame, RespVersion};
+
+/// Writes replies for a client that speaks a particular protocol version
+///
+/// Command handlers describe what they reply with, a map or a double for
+/// example, and the builder lowers it to whatever the connection speaks:
+/// RESP3 natively or the RESP2 equivalent Redis sends, so handlers never deal
+/// in protocol bytes. Aggregates are written as a header followed by their
+/// elements, like Redis' `addReply*Len`:
+///
+/// ```
+/// # use redis_server::{protocol::RespVersion, reply::ReplyBuilder};
+/// let mut out = Vec::new();
+/// let mut reply = ReplyBuilder::new(&mut out, RespVersion::Resp2);
+/// reply.map(1);
+/// reply.bulk(b"score");
+/// reply.double(1.5);
+/// assert_eq!(out, b"*2\r\n$5\r\nscore\r\n$3\r\n1.5\r\n");
+/// ```
+///
+/// When the shape isn't known up front, build a [`Frame`] and write it with
+/// [`ReplyBuilder::frame`] instead.
+pub struct ReplyBuilder<'a> {
+    out: &'a mut Vec<u8>,
+    version: RespVersion,
+}
+
+impl<'a> ReplyBuilder<'a> {
+    pub fn new(out: &'a mut Vec<u8>, version: RespVersion) -> Self {
+        Self { out, version }
+    }
+
+    pub fn version(&self) -> RespVersion {
+        self.version
+    }
+
+    /// Where the reply currently ends, for [`ReplyBuilder::rollback`]
+    pub fn checkpoint(&self) -> usize {
+        self.out.len()
+    }
+
+    /// Everything written since `checkpoint`
+    pub fn since(&self, checkpoint: usize) -> &[u8] {
+        &self.out[checkpoint..]
+    }
+
+    /// Throw away everything written since `checkpoint`
+    pub fn rollback(&mut self, checkpoint: usize) {
+        self.out.truncate(checkpoint);
+    }
+
+    /// `+OK`
+    pub fn ok(&mut self) {
+        self.simple("OK");
+    }
+
+    pub fn simple(&mut self, s: &str) {
+        self.line(b'+', s);
+    }
+
+    /// An error reply, `message` starting with its code (`ERR`, `WRONGTYPE`...)
+    ///
+    /// Line breaks would end the reply early, so they're turned into spaces.
+    pub fn error(&mut self, message: &str) {
+        if message.contains(['\r', '\n']) {
+            self.line(b'-', message.replace(['\r', '\n'], " "));
+        } else {
+            self.line(b'-', message);
+        }
+    }
+
+    pub fn integer(&mut self, n: i64) {
+        self.line(b':', n);
+    }
+
+    pub fn bulk(&mut self, data: &[u8]) {
+        self.line(b'$', data.len());
+        self.out.extend_from_slice(data);
+        self.out.extend_from_slice(b"\r\n");
+    }
+
+    /// A missing value; the null bulk string in RESP2
+    pub fn null(&mut self) {
+        match self.version {
+            RespVersion::Resp2 => self.out.extend_from_slice(b"$-1\r\n"),
+            RespVersion::Resp3 => self.out.extend_from_slice(b"_\r\n"),
+        }
+    }
+
+    /// A missing aggregate, like BLPOP timing out; the null array in RESP2
+    pub fn null_array(&mut self) {
+        match self.version {
+            RespVersion::Resp2 => self.out.extend_from_slice(b"*-1\r\n"),
+            RespVersion::Resp3 => self.out.extend_from_slice(b"_\r\n"),
+        }
+    }
+
+    /// Start an array of `len` elements
+    pub fn array(&mut self, len: usize) {
+        self.line(b'*', len);
+    }
+
+    /// Start a map of `len` key/value pairs, each written as a key followed
+    /// by its value; a flat array of `2 * len` elements in RESP2
+    pub fn map(&mut self, len: usize) {
+        match self.version {
+            RespVersion::Resp2 => self.line(b'*', len * 2),
+            RespVersion::Resp3 => self.line(b'%', len),
+        }
+    }
+
+    /// Start a set of `len` elements; an array in RESP2
+    pub fn set(&mut self, len: usize) {
+        match self.version {
+            RespVersion::Resp2 => self.line(b'*', len),
+            RespVersion::Resp3 => self.line(b'~', len),
+        }
+    }
+
+    /// Start an out-of-band push of `len` elements; an array in RESP2
+    pub fn push(&mut self, len: usize) {
+        match self.version {
+            RespVersion::Resp2 => self.line(b'*', len),
+            RespVersion::Resp3 => self.line(b'>', len),
+        }
+    }
+
+    /// A double; a bulk string in RESP2
+    pub fn double(&mut self, value: f64) {
+        let formatted = format_double(value);
+        match self.version {
+            RespVersion::Resp2 => self.bulk(formatted.as_bytes()),
+            RespVersion::Resp3 => self.line(b',', formatted),
+        }
+    }
+
+    /// A boolean; `:1` or `:0` in RESP2
+    pub fn boolean(&mut self, value: bool) {
+        match self.version {
+            RespVersion::Resp2 => self.integer(i64::from(value)),
+            RespVersion::Resp3 => self.line(b'#', if value { 't' } else { 'f' }),
+        }
+    }
+
+    /// An integer too big for an `i64`, given as its decimal digits; a bulk
+    /// string in RESP2
+    pub fn big_number(&mut self, digits: &str) {
+        match self.version {
+            RespVersion::Resp2 => self.bulk(digits.as_bytes()),
+            RespVersion::Resp3 => self.line(b'(', digits),
+        }
+    }
+
+    /// Text meant to be shown as is, tagged with a three letter `format`
+    /// like `txt` or `mkd`; a plain bulk string in RESP2
+    pub fn verbatim(&mut self, format: &str, text: &[u8]) {
+        debug_assert_eq!(format.len(), 3, "verbatim formats are 3 bytes");
+        match self.version {
+            RespVersion::Resp2 => self.bulk(text),
+            RespVersion::Resp3 => {
+                self.line(b'=', text.len() + 4);
+                self.out.extend_from_slice(format.as_bytes());
+                self.out.push(b':');
+                self.out.extend_from_slice(text);
+                self.out.extend_from_slice(b"\r\n");
+            }
+        }
+    }
+
+    /// Write a whole [`Frame`], lowering anything RESP2 lacks
+    ///
+    /// RESP2 has no way to carry attributes, so they're left out and only the
+    /// value they're attached to is written.
+    pub fn frame(&mut self, frame: &Frame) {
+        match frame {
+            Frame::Simple(s) => self.simple(s),
+            Frame::Error(e) => self.error(e),
+            Frame::Integer(n) => self.integer(*n),
+            Frame::Bulk(data) => self.bulk(data),
+            Frame::Null => self.null(),
+            Frame::Array(items) => {
+                self.array(items.len());
+                items.iter().for_each(|item| self.frame(item));
+            }
+            Frame::Push(items) => {
+                self.push(items.len());
+                items.iter().for_each(|item| self.frame(item));
+            }
+            Frame::Set(items) => {
+                self.set(items.len());
+                items.iter().for_each(|item| self.frame(item));
+            }
+            Frame::Map(pairs) => {
+                self.map(pairs.len());
+                for (key, value) in pairs {
+                    self.frame(key);
+                    self.frame(value);
+                }
+            }
+            Frame::Double(value) => self.double(*value),
+            Frame::Boolean(value) => self.boolean(*value),
+            Frame::BigNumber(digits) => self.big_number(digits),
+            Frame::Verbatim { format, text } => self.verbatim(format, text),
+            Frame::Attribute { attributes, value } => {
+                if self.version == RespVersion::Resp3 {
+                    self.line(b'|', attributes.len());
+                    for (key, value) in attributes {
+                        self.frame(key);
+                        self.frame(value);
+                    }
+                }
+                self.frame(value);
+            }
+        }
+    }
+
+    fn line(&mut self, kind: u8, value: impl std::fmt::Display) {
+        self.out.push(kind);
+        self.out.extend_from_slice(value.to_string().as_bytes());
+        self.out.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Format a double the way Redis does: the shortest representation that
+/// reads back as the same value, switching to an exponent for very large
+/// and very small magnitudes like `%.17g` would
+pub fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_owned();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_owned();
+    }
+
+    let magnitude = value.abs();
+    if magnitude != 0.0 && !(1e-4..1e17).contains(&magnitude) {
+        // Rust writes `1e300` and `1.5e-5`, C writes `1e+300` and `1.5e-05`
+        let formatted = format!("{:e}", value);
+        let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+        let (sign, digits) = match exponent.strip_prefix('-') {
+            Some(digits) => ('-', digits),
+            None => ('+', exponent),
+        };
+        return format!("{}e{}{:0>2}", mantissa, sign, digits);
+    }
+    format!("{}", value)
+}
@@ -0,0 +1,51 @@
+/// Number of hash slots keys are spread across in cluster mode
+pub const CLUSTER_SLOTS: u16 = 16384;
+
+/// CRC16-CCITT (XMODEM), the checksum Redis Cluster hashes keys with
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        let mut crc = crc ^ (u16::from(byte) << 8);
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// The part of `key` that decides its slot
+///
+/// If the key contains a `{...}` with at least one byte between the first
+/// `{` and the `}` after it, only those bytes are hashed, so related keys
+/// like `{user:1}:name` and `{user:1}:email` land on the same slot.
+/// Otherwise the whole key is.
+pub fn hash_tag(key: &[u8]) -> &[u8] {
+    let Some(open) = key.iter().position(|&b| b == b'{') else {
+        return key;
+    };
+    match key[open + 1..].iter().position(|&b| b == b'}') {
+        Some(len) if len > 0 => &key[open + 1..open + 1 + len],
+        _ => key,
+    }
+}
+
+/// The hash slot `key` belongs to, as reported by `CLUSTER KEYSLOT`
+pub fn key_slot(key: &[u8]) -> u16 {
+    crc16(hash_tag(key)) & (CLUSTER_SLOTS - 1)
+}
+
+/// The slot every key in `keys` shares, or `None` if they're spread across
+/// more than one (what a `-CROSSSLOT` check needs to know)
+///
+/// An empty list has no slot either.
+pub fn common_slot<'a, I>(keys: I) -> Option<u16>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut keys = keys.into_iter();
+    let slot = key_slot(keys.next()?);
+    keys.all(|key| key_slot(key) == slot).then_some(slot)
+}
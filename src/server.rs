@@ -1,17 +1,66 @@
 use std::{
+    collections::HashMap,
+    io,
     net::SocketAddr,
-    sync::{Arc, atomic::AtomicUsize, atomic::Ordering},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
 };
 
-use anyhow::Result;
-use tokio::net::{TcpListener, TcpStream};
+use anyhow::{Context, Result};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        TcpListener, TcpSocket, TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot, watch,
+    },
+    task::JoinHandle,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    allocator,
+    buffer_pool::{BufferPool, PooledBuffer},
+    command::{self, Command, CommandContext},
+    config::ServerConfig,
+    daemon,
+    hooks::CommandHook,
+    metrics,
+    protocol::{Frame, Request, RequestParser, RespVersion},
+    rate_limit::IpRateLimiter,
+    reply::ReplyBuilder,
+    stats::ServerStats,
+    supervisor::{self, Supervisor},
+};
+
+/// How long the accept loop pauses after running out of file descriptors
+pub(crate) const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Reply batches queued for a client's writer before the reader waits for
+/// it to catch up
+const REPLY_QUEUE_LEN: usize = 16;
+
+/// Out-of-band messages queued for a client before it is disconnected for
+/// not keeping up
+const PUSH_QUEUE_LEN: usize = 1024;
+
+/// How often shutdown checks whether the last client has gone
+const DRAIN_POLL: Duration = Duration::from_millis(20);
+
+/// How often shutdown logs how many clients it's still waiting for
+const DRAIN_STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Most bytes of the command name, and of its arguments, quoted back in an
+/// unknown command error
+const UNKNOWN_COMMAND_ARGS_LEN: usize = 128;
 
-/// Server Configuration file
-pub struct ServerConfig {
-    pub ip: String,
-    pub port: u16,
-    pub max_connections: usize,
-}
 /// The TCP Server implementation
 ///
 /// # Design Choices
@@ -19,10 +68,10 @@ pub struct ServerConfig {
 /// ## Notes on `.fetch_add` and `.fetch_sub`
 ///
 /// * These methods add and and subtract at the CPU level and typically compile
-/// down to a single CPU instruction with a `LOCK` prefix.
+///   down to a single CPU instruction with a `LOCK` prefix.
 ///     * This instruction provides exlusive access to the memory location.
 /// * `+ 1` and `- 1` are appended to `.fetch_add` and `.fetch_sub` as they
-/// return the number _before_ the operation occurred.
+///   return the number _before_ the operation occurred.
 ///
 /// ## A Short Discussion on `Ordering` Choice
 ///
@@ -57,83 +106,362 @@ pub struct ServerConfig {
 /// * `tokio::spawn` - Spawns a new thread.
 /// * `async move` - There are actually 2 things happening here:
 ///    * `aync` - Makes the following code return a Future, makes it
-///    `await`able, and therefore good to use in threads.
-///    [(rust-lang.github.io/async-book)](https://rust-lang.github.io/async-book/part-guide/async-await.html#async-functions)
+///      `await`able, and therefore good to use in threads.
+///      [(rust-lang.github.io/async-book)](https://rust-lang.github.io/async-book/part-guide/async-await.html#async-functions)
 ///     * `move` - Forces the closure to take ownership of all captured
-///     variables instead of borrowing
-///     them.[(doc.rust-lang)](https://doc.rust-lang.org/std/keyword.move.html)
+///       variables instead of borrowing
+///       them.[(doc.rust-lang)](https://doc.rust-lang.org/std/keyword.move.html)
 /// * `Arc::clone()` - Returns a new, reference-counted pointer to the `Arc`
-/// structure on the heap that is safe for multi-threading.
+///   structure on the heap that is safe for multi-threading.
 pub struct Server {
     config: ServerConfig,
     active_conns: Arc<AtomicUsize>,
-}
-
-impl ServerConfig {
-    /// Provide a simple default with a "reasonable" limit on connections
-    pub fn default() -> Self {
-        Self {
-            ip: "127.0.0.1".to_owned(),
-            port: 6379,
-            max_connections: 100,
-        }
-    }
+    total_conns: AtomicUsize,
+    rejected_conns: AtomicUsize,
+    overflowed_conns: AtomicUsize,
+    throttled_conns: AtomicUsize,
+    internal_errors: AtomicUsize,
+    buffers: Arc<BufferPool>,
+    tasks: Supervisor,
+    connection_rate: IpRateLimiter,
+    command_rate: IpRateLimiter,
+    next_client_id: AtomicU64,
+    /// Where to send pushes for each connected client, by client id
+    clients: Mutex<HashMap<u64, ClientHandle>>,
+    hooks: Vec<Arc<dyn CommandHook>>,
+    /// Commands added by the embedder, by lowercase name
+    commands: HashMap<String, Arc<dyn Command>>,
+    /// Flipped to true once shutdown starts, telling clients to finish up
+    closing: watch::Sender<bool>,
+    /// Flipped to true if shutdown stops waiting, disconnecting clients
+    /// whether or not they're done
+    aborting: watch::Sender<bool>,
 }
 
 impl Server {
     /// Create a new server instance with the specific server configurations
     pub fn new(config: ServerConfig) -> Arc<Self> {
+        Self::builder().config(config).build()
+    }
+
+    fn from_builder(builder: ServerBuilder) -> Arc<Self> {
+        let ServerBuilder {
+            mut config,
+            overrides,
+            hooks,
+            mut commands,
+        } = builder;
+        overrides.apply(&mut config);
+        rename_commands(&mut commands, &config.renamed_commands);
+
+        let buffers = BufferPool::new(config.buffer_size, config.buffer_pool_size);
+        let connection_rate = IpRateLimiter::new(config.per_ip_connection_rate);
+        let command_rate = IpRateLimiter::new(config.per_ip_command_rate);
+
         Arc::new(Self {
             config,
             active_conns: Arc::new(AtomicUsize::new(0)),
+            total_conns: AtomicUsize::new(0),
+            rejected_conns: AtomicUsize::new(0),
+            overflowed_conns: AtomicUsize::new(0),
+            throttled_conns: AtomicUsize::new(0),
+            internal_errors: AtomicUsize::new(0),
+            buffers,
+            tasks: Supervisor::new(),
+            connection_rate,
+            command_rate,
+            next_client_id: AtomicU64::new(1),
+            clients: Mutex::new(HashMap::new()),
+            hooks,
+            commands,
+            closing: watch::Sender::new(false),
+            aborting: watch::Sender::new(false),
         })
     }
 
+    /// Start configuring a server, see [`ServerBuilder`]
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder {
+            config: ServerConfig::default(),
+            overrides: Overrides::default(),
+            hooks: Vec::new(),
+            commands: HashMap::new(),
+        }
+    }
+
     /// Start up the Redis server to and listen in on connections
+    ///
+    /// Runs until the process receives `SIGINT` or `SIGTERM`, then waits for
+    /// connected clients to finish what they sent. A second signal while
+    /// waiting exits straight away. Embedders that want to stay in charge of
+    /// shutdown should use [`ServerBuilder::spawn`].
     pub async fn run(self: Arc<Self>) -> Result<()> {
+        let listeners = self.listen().await?;
+
+        let shutdown = async {
+            let signal = shutdown_signal().await;
+            info!("Received {}, scheduling shutdown...", signal);
+        };
+        let abort = async {
+            let signal = shutdown_signal().await;
+            warn!(
+                "Received {} again, exiting without waiting for clients",
+                signal
+            );
+        };
+        self.serve(listeners, shutdown, abort).await
+    }
+
+    /// Bind the client listener, and the metrics one if there is one
+    async fn listen(&self) -> Result<Listeners> {
         let addr = format!("{}:{}", self.config.ip, self.config.port);
-        let listener = TcpListener::bind(&addr).await?;
+        let clients = bind(&addr, self.config.tcp_backlog).await?;
+
+        let local_addr = clients.local_addr()?;
+        info!("Redis server starting... {}", local_addr);
+        info!("Running mode=standalone, port={}.", local_addr.port());
+
+        let metrics = match self.config.metrics_port {
+            Some(port) => {
+                let metrics_addr = format!("{}:{}", self.config.ip, port);
+                let listener = TcpListener::bind(&metrics_addr)
+                    .await
+                    .with_context(|| format!("can't serve metrics on {}", metrics_addr))?;
+                info!(
+                    "Serving Prometheus metrics on http://{}/metrics",
+                    listener.local_addr()?
+                );
+                Some(listener)
+            }
+            None => None,
+        };
+
+        Ok(Listeners { clients, metrics })
+    }
 
-        println!("Redis server starting... {}", &addr);
+    /// Accept connections until `shutdown` completes, then [`Server::drain`]
+    /// the clients unless `abort` completes first
+    async fn serve(
+        self: Arc<Self>,
+        listeners: Listeners,
+        shutdown: impl Future<Output = ()>,
+        abort: impl Future<Output = ()>,
+    ) -> Result<()> {
+        let Listeners {
+            clients: listener,
+            metrics,
+        } = listeners;
 
-        while self.active_conns.load(Ordering::Relaxed) < self.config.max_connections {
+        if let Some(metrics_listener) = metrics {
+            let metrics_listener = Arc::new(metrics_listener);
+            let server = Arc::clone(&self);
+            self.tasks.spawn("metrics", move || {
+                metrics::serve(Arc::clone(&metrics_listener), Arc::clone(&server))
+            });
+        }
+
+        if self.config.notify_systemd() {
+            daemon::sd_notify("READY=1\nSTATUS=Ready to accept connections");
+        }
+
+        tokio::pin!(shutdown);
+        loop {
             tokio::select! {
-                result = listener.accept() => {
-                    let (socket, addr) = result?;
-                    println!("{}", addr);
-
-                    // let server = self.clone();
-                    let server = Arc::clone(&self);
-                    // let active_conns = self.active_conns.clone();
-                    let active_conns = Arc::clone(&self.active_conns);
-
-                    tokio::spawn(async move {
-                        let count = active_conns.fetch_add(1, Ordering::Relaxed) + 1;
-                        println!("Processing {} (active connections: {})", addr, count);
-
-                        server.handle_connection(socket, addr).await;
-                        println!("Client addr: {}", addr);
-                        println!("Active connections: {}", active_conns.load(Ordering::Relaxed));
-
-                        let count = active_conns.fetch_sub(1, Ordering::Relaxed) - 1;
-                        println!("Finished {} (active connections: {})", addr, count);
-                    });
-                }
+                result = listener.accept() => match result {
+                    Ok((socket, addr)) => self.accept(socket, addr),
+                    Err(e) => self.accept_failed(e).await,
+                },
 
-                _ = tokio::signal::ctrl_c() => {
-                    self.shutdown();
-                    break;
-                }
+                _ = &mut shutdown => break,
             }
         }
+
+        // Refuse new connections while the existing ones wind down
+        drop(listener);
+        self.drain(abort).await;
+        self.shutdown();
         Ok(())
     }
 
+    /// Tell every client to finish up, and wait until they have
+    ///
+    /// A client finishes the requests it already sent and gets their replies
+    /// before being disconnected. One that doesn't read its replies can hold
+    /// shutdown up indefinitely, which is what `abort` is for: once it
+    /// completes, every client still connected is cut off.
+    async fn drain(&self, abort: impl Future<Output = ()>) {
+        self.closing.send_replace(true);
+        if self.config.notify_systemd() {
+            daemon::sd_notify("STOPPING=1");
+        }
+
+        tokio::pin!(abort);
+        let mut poll = tokio::time::interval(DRAIN_POLL);
+        let mut status = tokio::time::interval(DRAIN_STATUS_INTERVAL);
+        // The first tick of an interval is immediate, skip logging for
+        // clients that are about to go anyway
+        status.tick().await;
+
+        loop {
+            let remaining = self.active_conns.load(Ordering::Relaxed);
+            if remaining == 0 {
+                return;
+            }
+
+            tokio::select! {
+                _ = poll.tick() => {}
+
+                _ = status.tick() => {
+                    info!(
+                        "Waiting for {} clients to disconnect (Ctrl+C again to exit now)",
+                        remaining
+                    );
+                }
+
+                _ = &mut abort => break,
+            }
+        }
+
+        // Connections notice straight away, wherever they're waiting
+        self.aborting.send_replace(true);
+        while self.active_conns.load(Ordering::Relaxed) > 0 {
+            poll.tick().await;
+        }
+    }
+
+    /// Hand a freshly accepted connection off to its own task, or turn it
+    /// away if we're already at `max_connections` or its IP is connecting
+    /// too often
+    fn accept(self: &Arc<Self>, socket: TcpStream, addr: SocketAddr) {
+        debug!("Accepted {}", addr);
+        self.total_conns.fetch_add(1, Ordering::Relaxed);
+
+        if !self.connection_rate.try_acquire(addr.ip()) {
+            self.throttled_conns.fetch_add(1, Ordering::Relaxed);
+            debug!("Rejected {}: connection rate limit reached", addr);
+            reject(socket, b"-ERR max connection rate per IP reached\r\n");
+            return;
+        }
+
+        // Reserve the slot here rather than in the spawned task, otherwise a
+        // burst of connections could all pass the check before any of them
+        // are counted
+        let count = self.active_conns.fetch_add(1, Ordering::Relaxed) + 1;
+        if count > self.config.max_connections {
+            self.active_conns.fetch_sub(1, Ordering::Relaxed);
+            self.rejected_conns.fetch_add(1, Ordering::Relaxed);
+            warn!("Rejected {}: max number of clients reached", addr);
+            reject(socket, b"-ERR max number of clients reached\r\n");
+            return;
+        }
+
+        // let server = self.clone();
+        let server = Arc::clone(self);
+        // let active_conns = self.active_conns.clone();
+        let active_conns = Arc::clone(&self.active_conns);
+
+        tokio::spawn(async move {
+            debug!("Processing {} (active connections: {})", addr, count);
+
+            server.handle_connection(socket, addr).await;
+            debug!("Client addr: {}", addr);
+            debug!(
+                "Active connections: {}",
+                active_conns.load(Ordering::Relaxed)
+            );
+
+            let count = active_conns.fetch_sub(1, Ordering::Relaxed) - 1;
+            debug!("Finished {} (active connections: {})", addr, count);
+        });
+    }
+
+    /// Decide what a failed `accept()` means for the accept loop
+    ///
+    /// None of these are fatal for the server as a whole. Running out of file
+    /// descriptors is the interesting case: the pending connection stays in
+    /// the backlog, so accepting again straight away would just spin. Back off
+    /// briefly to give existing connections a chance to close.
+    async fn accept_failed(&self, e: io::Error) {
+        if is_fd_exhaustion(&e) {
+            self.overflowed_conns.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "Out of file descriptors, pausing accepts for {}ms: {}",
+                ACCEPT_BACKOFF.as_millis(),
+                e
+            );
+            tokio::time::sleep(ACCEPT_BACKOFF).await;
+        } else {
+            // Typically the client hung up before we got to it
+            debug!("Accepting client connection: {}", e);
+        }
+    }
+
+    /// Snapshot of the server's counters
+    pub fn stats(&self) -> ServerStats {
+        ServerStats {
+            connected_clients: self.active_conns.load(Ordering::Relaxed),
+            total_connections_received: self.total_conns.load(Ordering::Relaxed),
+            rejected_connections: self.rejected_conns.load(Ordering::Relaxed),
+            overflowed_connections: self.overflowed_conns.load(Ordering::Relaxed),
+            throttled_connections: self.throttled_conns.load(Ordering::Relaxed),
+            internal_errors: self.internal_errors.load(Ordering::Relaxed),
+            buffer_pool: self.buffers.stats(),
+            allocator: allocator::stats(),
+            tasks: self.tasks.health(),
+        }
+    }
+
     /// Shutdown the server with commands
     pub fn shutdown(self: Arc<Self>) {
-        let final_count = &self.active_conns.load(Ordering::Relaxed);
-        println!("Active connections: {}", final_count);
-        println!("Ctrl + c detected, shutting down...")
+        let stats = self.stats();
+        info!("Active connections: {}", stats.connected_clients);
+        info!(
+            "Buffer pool: {} hits, {} misses, {} idle (hit rate: {:.2})",
+            stats.buffer_pool.hits,
+            stats.buffer_pool.misses,
+            stats.buffer_pool.idle,
+            stats.buffer_pool.hit_rate()
+        );
+        for task in stats.tasks.iter().filter(|task| task.restarts > 0) {
+            warn!(
+                "Background task '{}' was restarted {} times",
+                task.name, task.restarts
+            );
+        }
+        info!("Shutting down...");
+        self.tasks.shutdown();
+    }
+
+    /// Ids of the currently connected clients
+    pub fn client_ids(&self) -> Vec<u64> {
+        self.clients.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Queue an out-of-band message for client `id`
+    ///
+    /// Pushes are written between replies, never in the middle of one. A
+    /// client that falls [`PUSH_QUEUE_LEN`] messages behind is disconnected,
+    /// like Redis does once a client goes over its output buffer limit.
+    /// Returns false if the message wasn't queued.
+    pub fn push(&self, id: u64, frame: Frame) -> bool {
+        let mut clients = self.clients.lock().unwrap();
+        let Some(client) = clients.get(&id) else {
+            return false;
+        };
+
+        match client.pushes.try_send(frame) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                warn!(
+                    "Client id={} scheduled to be closed ASAP for overcoming of output buffer limits",
+                    id
+                );
+                // Dropping the handle is what tells the connection to close
+                clients.remove(&id);
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
     }
 
     /// Connection handler that carries out requests on the Redis server.
@@ -142,9 +470,664 @@ impl Server {
         socket: TcpStream,
         addr: SocketAddr,
     ) {
-        // Using `sleep` for now to simulate some work in the future
-        tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-        // Just using `socket` to keep Rust from complaining
-        assert_eq!(addr, socket.peer_addr().unwrap());
+        if let Err(e) = self.serve_client(socket, addr).await {
+            debug!("Client {} disconnected: {}", addr, e);
+        }
+    }
+
+    /// Answer requests from the client until it hangs up or breaks the
+    /// protocol
+    ///
+    /// The socket is split in two. This task reads and executes requests,
+    /// while a writer task of its own sends the replies along with any
+    /// pushes queued for the client. That way the reader never waits on a
+    /// slow client to drain its output, and writes for the client can
+    /// happen while the reader is waiting for input.
+    async fn serve_client(&self, socket: TcpStream, addr: SocketAddr) -> io::Result<()> {
+        let (mut reader, writer) = socket.into_split();
+        let (replies, queued) = mpsc::channel(REPLY_QUEUE_LEN);
+        let (client, pushes, evicted) = self.register_client();
+        let mut aborting = self.aborting.subscribe();
+
+        // Until HELLO exists every client speaks RESP2
+        let version = RespVersion::Resp2;
+        let writer = tokio::spawn(write_replies(
+            writer,
+            queued,
+            pushes,
+            evicted,
+            self.aborting.subscribe(),
+            version,
+        ));
+
+        // An aborted shutdown drops the reader wherever it is, including
+        // waiting for room in a full reply queue
+        let read = tokio::select! {
+            read = self.read_requests(&mut reader, &replies, client.id, addr, version) => read,
+            _ = aborting.wait_for(|&aborting| aborting) => Ok(()),
+        };
+
+        // The writer finishes sending whatever is queued once the reader is
+        // gone, and then shuts the connection down
+        drop(replies);
+        let written = writer.await.unwrap_or_else(|e| Err(io::Error::other(e)));
+        read.and(written)
+    }
+
+    /// Read requests and queue up their replies
+    ///
+    /// Everything already buffered is executed before queueing, so pipelined
+    /// requests get their replies back in a single write.
+    async fn read_requests(
+        &self,
+        socket: &mut OwnedReadHalf,
+        replies: &mpsc::Sender<PooledBuffer>,
+        client_id: u64,
+        addr: SocketAddr,
+        version: RespVersion,
+    ) -> io::Result<()> {
+        // Checked out for the lifetime of the connection; handed back to the
+        // pool when it goes out of scope
+        let mut read_buf = self.buffers.acquire();
+        let mut parser = RequestParser::new(self.config.request_limits());
+        let mut closing = self.closing.subscribe();
+        // A request that had to wait for its command-rate token, whose token
+        // is taken by now
+        let mut throttled: Option<Request> = None;
+
+        loop {
+            // Reply buffers go to the writer, which drops them back into the
+            // pool once they're written. One is only checked out once there's
+            // a reply to write
+            let mut write_buf: Option<PooledBuffer> = None;
+            let mut consumed = 0;
+            let mut throttle = None;
+            let mut panicked = false;
+            let parsed = loop {
+                let args = match throttled.take() {
+                    Some(args) => args,
+                    None => match parser.parse(&read_buf[consumed..]) {
+                        Ok((Some(args), len)) => {
+                            consumed += len;
+                            if args.is_empty() {
+                                continue;
+                            }
+                            // Over the command rate, hold on to the request
+                            // until its turn comes
+                            let wait = self.command_rate.reserve(addr.ip());
+                            if !wait.is_zero() {
+                                throttled = Some(args);
+                                throttle = Some(wait);
+                                break Ok(());
+                            }
+                            args
+                        }
+                        Ok((None, len)) => {
+                            consumed += len;
+                            break Ok(());
+                        }
+                        Err(e) => break Err(e),
+                    },
+                };
+
+                let write_buf = write_buf.get_or_insert_with(|| self.buffers.acquire());
+                let mut reply = ReplyBuilder::new(write_buf, version);
+                let command = CommandContext {
+                    client_id,
+                    addr,
+                    args: &args,
+                };
+                if !self.execute_guarded(&command, &mut reply) {
+                    panicked = true;
+                    break Ok(());
+                }
+            };
+            read_buf.drain(..consumed);
+
+            if let Err(e) = parsed {
+                // Like Redis, reply to whatever came before the bad request,
+                // explain what went wrong, then hang up: there's no telling
+                // where the next request would start
+                debug!("Protocol error ({}) from client {}", e, addr);
+                let mut write_buf = write_buf.unwrap_or_else(|| self.buffers.acquire());
+                ReplyBuilder::new(&mut write_buf, version)
+                    .error(&format!("ERR Protocol error: {}", e));
+                let _ = replies.send(write_buf).await;
+                return Ok(());
+            }
+
+            let write_buf = write_buf.filter(|buf| !buf.is_empty());
+            if panicked {
+                if let Some(write_buf) = write_buf {
+                    let _ = replies.send(write_buf).await;
+                }
+                return Ok(());
+            }
+
+            // Fails only once the writer has given up on the connection
+            if let Some(write_buf) = write_buf
+                && replies.send(write_buf).await.is_err()
+            {
+                return Ok(());
+            }
+
+            // Replies for what already ran have been queued; wait for the
+            // next request's turn, then carry on with what's still buffered
+            if let Some(wait) = throttle {
+                debug!("Throttling client {} for {}ms", addr, wait.as_millis());
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+
+                    // Shutting down doesn't wait for the throttle
+                    _ = closing.wait_for(|&closing| closing) => return Ok(()),
+                }
+                continue;
+            }
+
+            // Whatever is left over, along with what the parser kept of it,
+            // is (the start of) a single request that hasn't fully arrived.
+            // The per-argument limits already bound it, but a request can
+            // have a lot of arguments
+            let pending = read_buf.len() + parser.pending_len();
+            if pending > self.config.client_query_buffer_limit {
+                warn!(
+                    "Closing client {} that reached max query buffer length ({} bytes)",
+                    addr, pending
+                );
+                return Ok(());
+            }
+
+            // Only grow the buffer once a request doesn't fit in it. A buffer
+            // that grew past `buffer_size` can't go back to the pool
+            if read_buf.len() == read_buf.capacity() {
+                read_buf.reserve(self.config.buffer_size);
+            }
+            tokio::select! {
+                read = socket.read_buf(&mut *read_buf) => {
+                    if read? == 0 {
+                        return Ok(());
+                    }
+                }
+
+                // The writer hit an error or dropped the client for falling
+                // behind on pushes
+                _ = replies.closed() => return Ok(()),
+
+                // The server is shutting down. Whatever was read so far has
+                // been answered
+                _ = closing.wait_for(|&closing| closing) => return Ok(()),
+            }
+        }
+    }
+
+    fn register_client(
+        &self,
+    ) -> (
+        Registration<'_>,
+        mpsc::Receiver<Frame>,
+        oneshot::Receiver<()>,
+    ) {
+        let id = self.next_client_id.fetch_add(1, Ordering::Relaxed);
+        let (pushes, receiver) = mpsc::channel(PUSH_QUEUE_LEN);
+        let (evict, evicted) = oneshot::channel();
+
+        let client = ClientHandle {
+            pushes,
+            _evict: evict,
+        };
+        self.clients.lock().unwrap().insert(id, client);
+        (Registration { server: self, id }, receiver, evicted)
+    }
+
+    /// Run [`Server::execute`] and the command hooks around it, answering
+    /// `-ERR internal error` if any of them panics
+    ///
+    /// Returns false after a panic. The connection should then be closed,
+    /// since whatever the handler left half done can't be trusted.
+    fn execute_guarded(&self, command: &CommandContext, reply: &mut ReplyBuilder) -> bool {
+        let checkpoint = reply.checkpoint();
+        let run = || {
+            for hook in &self.hooks {
+                hook.before(command);
+            }
+            self.execute(command, reply);
+            for hook in &self.hooks {
+                hook.after(command, reply.since(checkpoint));
+            }
+        };
+        let Err(payload) = panic::catch_unwind(AssertUnwindSafe(run)) else {
+            return true;
+        };
+
+        // Drop any partial reply, it would desync the client
+        reply.rollback(checkpoint);
+        reply.error("ERR internal error");
+        self.internal_errors.fetch_add(1, Ordering::Relaxed);
+        error!(
+            "Command '{}' from client {} panicked: {}",
+            String::from_utf8_lossy(command.name()),
+            command.addr,
+            supervisor::panic_message(payload)
+        );
+        false
+    }
+
+    /// Run a single command, writing its reply to `reply`
+    fn execute(&self, command: &CommandContext, reply: &mut ReplyBuilder) {
+        let args = command.args;
+        let name = String::from_utf8_lossy(command.name()).to_lowercase();
+        if let Some(handler) = self.commands.get(&name) {
+            if command::arity_allows(handler.arity(), args.len()) {
+                handler.execute(command, reply);
+            } else {
+                reply.error(&format!(
+                    "ERR wrong number of arguments for '{}' command",
+                    name
+                ));
+            }
+            return;
+        }
+
+        // Like Redis, quote no more than 128 bytes of the name and of the
+        // arguments, or the error could be as big as the request
+        let mut quoted = String::new();
+        for arg in &args[1..] {
+            if quoted.len() >= UNKNOWN_COMMAND_ARGS_LEN {
+                break;
+            }
+            let room = UNKNOWN_COMMAND_ARGS_LEN - quoted.len();
+            let arg = &arg[..arg.len().min(room)];
+            quoted.push_str(&format!("'{}' ", String::from_utf8_lossy(arg)));
+        }
+        let name = &args[0][..args[0].len().min(UNKNOWN_COMMAND_ARGS_LEN)];
+        let message = format!(
+            "ERR unknown command '{}', with args beginning with: {}",
+            String::from_utf8_lossy(name),
+            quoted
+        );
+        reply.error(&message);
+    }
+}
+
+/// Write replies in the order they were queued, and pushes in between them
+///
+/// A reply is always written in full before a push, so pushes never end up
+/// inside one. Returns once the reader is done (after writing everything it
+/// queued), or as soon as the client is evicted for falling too far behind
+/// on pushes or when shutdown is aborted. A client that far behind has likely
+/// stopped reading, so neither waits for the write in progress.
+async fn write_replies(
+    mut socket: OwnedWriteHalf,
+    replies: mpsc::Receiver<PooledBuffer>,
+    pushes: mpsc::Receiver<Frame>,
+    evicted: oneshot::Receiver<()>,
+    mut aborting: watch::Receiver<bool>,
+    version: RespVersion,
+) -> io::Result<()> {
+    tokio::select! {
+        written = write_queued(&mut socket, replies, pushes, version) => written?,
+
+        // Dropping the socket abandons whatever it still had to send
+        _ = evicted => return Ok(()),
+        _ = aborting.wait_for(|&aborting| aborting) => return Ok(()),
+    }
+
+    socket.shutdown().await
+}
+
+/// The body of [`write_replies`], until there's nothing more to write
+async fn write_queued(
+    socket: &mut OwnedWriteHalf,
+    mut replies: mpsc::Receiver<PooledBuffer>,
+    mut pushes: mpsc::Receiver<Frame>,
+    version: RespVersion,
+) -> io::Result<()> {
+    let mut out = Vec::new();
+
+    loop {
+        tokio::select! {
+            biased;
+
+            reply = replies.recv() => match reply {
+                Some(reply) => socket.write_all(&reply).await?,
+                None => break,
+            },
+
+            push = pushes.recv() => {
+                // The sender is only dropped when we fell too far behind
+                let Some(frame) = push else {
+                    break;
+                };
+                out.clear();
+                frame.encode(version, &mut out);
+                while let Ok(frame) = pushes.try_recv() {
+                    frame.encode(version, &mut out);
+                }
+                socket.write_all(&out).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Where to reach a connected client from outside its connection
+struct ClientHandle {
+    pushes: mpsc::Sender<Frame>,
+    /// Dropped, along with the rest of the handle, to evict the client
+    _evict: oneshot::Sender<()>,
+}
+
+/// Removes a client from [`Server::clients`] when its connection ends
+struct Registration<'a> {
+    server: &'a Server,
+    id: u64,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.server.clients.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Builder for a [`Server`], starting from [`ServerConfig::default`]
+///
+/// Settings made with [`ip`](Self::ip), [`port`](Self::port) and
+/// [`max_connections`](Self::max_connections) take precedence over the
+/// [`config`](Self::config), whichever order they're called in.
+///
+/// ```no_run
+/// # async fn example() -> anyhow::Result<()> {
+/// let handle = redis_server::Server::builder().port(0).spawn().await?;
+/// println!("listening on {}", handle.local_addr());
+/// handle.shutdown().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ServerBuilder {
+    config: ServerConfig,
+    overrides: Overrides,
+    hooks: Vec<Arc<dyn CommandHook>>,
+    commands: HashMap<String, Arc<dyn Command>>,
+}
+
+impl ServerBuilder {
+    /// Address to listen on
+    pub fn ip(mut self, ip: impl Into<String>) -> Self {
+        self.overrides.ip = Some(ip.into());
+        self
+    }
+
+    /// Port to listen on; `0` picks a free ephemeral port
+    pub fn port(mut self, port: u16) -> Self {
+        self.overrides.port = Some(port);
+        self
+    }
+
+    /// Maximum number of clients connected at once
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.overrides.max_connections = Some(max_connections);
+        self
+    }
+
+    /// Start from `config` instead of the defaults
+    ///
+    /// Replaces any earlier `config`, but not the settings made with the
+    /// other methods.
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Call `hook` around every command, after any hooks added before it
+    pub fn hook(mut self, hook: impl CommandHook) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Let clients call `command`
+    ///
+    /// # Panics
+    ///
+    /// If a command with the same name was already added.
+    pub fn command(mut self, command: impl Command) -> Self {
+        let name = command.name().to_lowercase();
+        if let Some(previous) = self.commands.insert(name, Arc::new(command)) {
+            panic!("command '{}' added twice", previous.name());
+        }
+        self
+    }
+
+    /// Create the server without starting it, e.g. to [`Server::run`] it
+    pub fn build(self) -> Arc<Server> {
+        Server::from_builder(self)
+    }
+
+    /// Bind the listener and serve connections on a background task
+    ///
+    /// The returned handle knows the address that was actually bound, which
+    /// is what makes `port(0)` useful.
+    pub async fn spawn(self) -> Result<ServerHandle> {
+        let server = self.build();
+        let listeners = server.listen().await?;
+        let local_addr = listeners.clients.local_addr()?;
+        let metrics_addr = listeners
+            .metrics
+            .as_ref()
+            .map(TcpListener::local_addr)
+            .transpose()?;
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let shutdown = async {
+            // A dropped handle counts as a request to shut down too
+            let _ = shutdown_rx.await;
+        };
+        let (abort_tx, abort_rx) = oneshot::channel();
+        let abort = async {
+            // But not as a request to cut the clients off
+            if abort_rx.await.is_err() {
+                std::future::pending().await
+            }
+        };
+        let task = tokio::spawn(Arc::clone(&server).serve(listeners, shutdown, abort));
+
+        Ok(ServerHandle {
+            server,
+            local_addr,
+            metrics_addr,
+            shutdown_tx,
+            abort_tx,
+            task,
+        })
+    }
+}
+
+/// The sockets a server accepts connections on
+struct Listeners {
+    clients: TcpListener,
+    metrics: Option<TcpListener>,
+}
+
+/// Settings made on the builder directly, applied on top of its config
+#[derive(Default)]
+struct Overrides {
+    ip: Option<String>,
+    port: Option<u16>,
+    max_connections: Option<usize>,
+}
+
+impl Overrides {
+    fn apply(self, config: &mut ServerConfig) {
+        if let Some(ip) = self.ip {
+            config.ip = ip;
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(max_connections) = self.max_connections {
+            config.max_connections = max_connections;
+        }
+    }
+}
+
+/// A server running on a background task, see [`ServerBuilder::spawn`]
+///
+/// Dropping the handle shuts the server down.
+pub struct ServerHandle {
+    server: Arc<Server>,
+    local_addr: SocketAddr,
+    metrics_addr: Option<SocketAddr>,
+    shutdown_tx: oneshot::Sender<()>,
+    abort_tx: oneshot::Sender<()>,
+    task: JoinHandle<Result<()>>,
+}
+
+impl ServerHandle {
+    /// The address the client listener is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The address the metrics endpoint is bound to, if it's enabled
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr
+    }
+
+    /// The running server, e.g. to read its [`Server::stats`]
+    pub fn server(&self) -> &Arc<Server> {
+        &self.server
+    }
+
+    /// Stop accepting connections and wait for connected clients to finish
+    /// what they sent and disconnect
+    ///
+    /// A client that stops reading its replies keeps this waiting for as
+    /// long as it stays connected; [`shutdown_timeout`](Self::shutdown_timeout)
+    /// puts a limit on that.
+    pub async fn shutdown(self) -> Result<()> {
+        let _ = self.shutdown_tx.send(());
+        self.task.await?
+    }
+
+    /// Like [`shutdown`](Self::shutdown), but after waiting `timeout` for
+    /// clients to finish, disconnect the ones that haven't
+    ///
+    /// Their unsent replies are dropped. A zero `timeout` disconnects every
+    /// client straight away.
+    pub async fn shutdown_timeout(self, timeout: Duration) -> Result<()> {
+        let Self {
+            shutdown_tx,
+            abort_tx,
+            mut task,
+            ..
+        } = self;
+        let _ = shutdown_tx.send(());
+        if let Ok(stopped) = tokio::time::timeout(timeout, &mut task).await {
+            return stopped?;
+        }
+
+        warn!(
+            "Clients still connected after {}ms, disconnecting them",
+            timeout.as_millis()
+        );
+        let _ = abort_tx.send(());
+        task.await?
+    }
+}
+
+/// Apply `rename-command` to the command table
+///
+/// Redis refuses to start when asked to rename a command it doesn't have.
+/// Most of Redis' commands don't exist here yet, so a config written for
+/// Redis would never start; such renames are logged and skipped instead.
+fn rename_commands(commands: &mut HashMap<String, Arc<dyn Command>>, renames: &[(String, String)]) {
+    for (from, to) in renames {
+        if !to.is_empty() && commands.contains_key(to) {
+            warn!(
+                "Can't rename command '{}' to '{}': target command name already exists",
+                from, to
+            );
+            continue;
+        }
+        let Some(command) = commands.remove(from) else {
+            warn!("Ignoring rename-command for unknown command '{}'", from);
+            continue;
+        };
+        if to.is_empty() {
+            info!("Command '{}' disabled by rename-command", from);
+        } else {
+            commands.insert(to.clone(), command);
+        }
+    }
+}
+
+/// Wait for a request to shut down, returning the name of the signal
+///
+/// `SIGTERM` matters as much as Ctrl + c (`SIGINT`) here: it's what service
+/// managers send when stopping a daemonized server.
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => return "SIGINT",
+                _ = terminate.recv() => return "SIGTERM",
+            }
+        }
+    }
+
+    let _ = tokio::signal::ctrl_c().await;
+    "SIGINT"
+}
+
+/// Send a client we won't serve a parting error, without holding up the
+/// accept loop
+fn reject(mut socket: TcpStream, message: &'static [u8]) {
+    tokio::spawn(async move {
+        let _ = socket.write_all(message).await;
+    });
+}
+
+/// Bind a listener with an explicit `listen(2)` backlog
+///
+/// `TcpListener::bind` always uses a backlog of 1024, which isn't what
+/// `tcp-backlog` asks for. The kernel may still clamp it to `somaxconn`.
+async fn bind(addr: &str, backlog: u32) -> Result<TcpListener> {
+    let mut last_err = None;
+
+    for addr in tokio::net::lookup_host(addr).await? {
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        #[cfg(unix)]
+        socket.set_reuseaddr(true)?;
+
+        match socket.bind(addr).and_then(|()| socket.listen(backlog)) {
+            Ok(listener) => return Ok(listener),
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    match last_err {
+        Some(e) => Err(e).with_context(|| format!("can't listen on {}", addr)),
+        None => anyhow::bail!("{} didn't resolve to any address", addr),
+    }
+}
+
+/// Did `accept()` fail because this process (`EMFILE`) or the whole system
+/// (`ENFILE`) ran out of file descriptors?
+fn is_fd_exhaustion(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        matches!(e.raw_os_error(), Some(libc::EMFILE | libc::ENFILE))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = e;
+        false
     }
 }
@@ -0,0 +1,23 @@
+use crate::{allocator::AllocatorStats, buffer_pool::BufferPoolStats, supervisor::TaskHealth};
+
+/// Point-in-time snapshot of the server counters
+///
+/// This is the one place the reporting surfaces (currently the Prometheus
+/// endpoint and the shutdown log) read from, so they never disagree.
+pub struct ServerStats {
+    pub connected_clients: usize,
+    pub total_connections_received: usize,
+    /// Connections turned away because `max_connections` was reached
+    pub rejected_connections: usize,
+    /// Failed accepts because the process or system ran out of file descriptors
+    pub overflowed_connections: usize,
+    /// Connections turned away by the per-IP connection rate limit
+    pub throttled_connections: usize,
+    /// Commands that panicked and were answered with `-ERR internal error`
+    pub internal_errors: usize,
+    pub buffer_pool: BufferPoolStats,
+    /// `None` with the system allocator, which doesn't keep counters
+    pub allocator: Option<AllocatorStats>,
+    /// Background tasks like the metrics listener
+    pub tasks: Vec<TaskHealth>,
+}
@@ -0,0 +1,160 @@
+use std::{
+    any::Any,
+    future::Future,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// Delay before the first restart of a task that panicked
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Restarts back off exponentially up to this
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Keeps the server's long-running background tasks alive
+///
+/// A supervised task that panics is logged and restarted after a backoff
+/// which doubles with every consecutive panic. Once a task has stayed up for
+/// [`MAX_BACKOFF`] the backoff starts over. A task that returns normally is
+/// considered done and is not restarted.
+///
+/// Dropping the supervisor doesn't stop anything; call
+/// [`Supervisor::shutdown`] for that.
+#[derive(Default)]
+pub struct Supervisor {
+    tasks: Mutex<Vec<Supervised>>,
+}
+
+struct Supervised {
+    name: &'static str,
+    state: Arc<TaskState>,
+    handle: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct TaskState {
+    running: AtomicBool,
+    restarts: AtomicUsize,
+    last_panic: Mutex<Option<String>>,
+}
+
+/// Snapshot of one supervised task
+pub struct TaskHealth {
+    pub name: &'static str,
+    /// False once the task has finished, or while it waits to be restarted
+    pub running: bool,
+    pub restarts: usize,
+    /// The message of the most recent panic, if it ever panicked
+    pub last_panic: Option<String>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the future made by `task` in the background, making a fresh one
+    /// to restart it whenever it panics
+    pub fn spawn<F, Fut>(&self, name: &'static str, task: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let state = Arc::new(TaskState::default());
+        let handle = tokio::spawn(supervise(name, task, Arc::clone(&state)));
+
+        self.tasks.lock().unwrap().push(Supervised {
+            name,
+            state,
+            handle,
+        });
+    }
+
+    pub fn health(&self) -> Vec<TaskHealth> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|task| TaskHealth {
+                name: task.name,
+                running: task.state.running.load(Ordering::Relaxed),
+                restarts: task.state.restarts.load(Ordering::Relaxed),
+                last_panic: task.state.last_panic.lock().unwrap().clone(),
+            })
+            .collect()
+    }
+
+    /// Stop every supervised task
+    pub fn shutdown(&self) {
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.handle.abort();
+        }
+    }
+}
+
+async fn supervise<F, Fut>(name: &'static str, task: F, state: Arc<TaskState>)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        let started = Instant::now();
+        state.running.store(true, Ordering::Relaxed);
+
+        // The guard makes aborting the supervisor abort the task as well
+        let mut run = AbortOnDrop(tokio::spawn(task()));
+        let result = (&mut run.0).await;
+        state.running.store(false, Ordering::Relaxed);
+
+        let panic = match result {
+            Ok(()) => return,
+            Err(e) if e.is_panic() => panic_message(e.into_panic()),
+            // Only we abort the task, and then we're being dropped too
+            Err(_) => return,
+        };
+
+        if started.elapsed() >= MAX_BACKOFF {
+            backoff = MIN_BACKOFF;
+        }
+        error!(
+            "Background task '{}' panicked: {}, restarting in {}ms",
+            name,
+            panic,
+            backoff.as_millis()
+        );
+        *state.last_panic.lock().unwrap() = Some(panic);
+
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+
+        let restarts = state.restarts.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!("Restarting background task '{}' ({})", name, restarts);
+    }
+}
+
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// The message a panic was raised with, if it had one
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
+}
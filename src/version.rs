@@ -0,0 +1,24 @@
+//! Build information, filled in by `build.rs`
+
+use crate::allocator;
+
+/// The crate version, reported where Redis reports `redis_version`
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// First 8 hex digits of the commit the server was built from
+pub const GIT_SHA1: &str = env!("REDIS_GIT_SHA1");
+
+/// Whether the working tree had uncommitted changes at build time
+pub const GIT_DIRTY: bool = matches!(env!("REDIS_GIT_DIRTY").as_bytes(), b"1");
+
+/// The one-liner `redis-server --version` prints
+pub fn describe() -> String {
+    format!(
+        "Redis server v={} sha={}:{} malloc={} bits={}",
+        VERSION,
+        GIT_SHA1,
+        u8::from(GIT_DIRTY),
+        allocator::NAME,
+        usize::BITS
+    )
+}
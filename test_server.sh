@@ -7,8 +7,9 @@ NUM_CONNS=${1:-1}
 for ((i=1; i<=NUM_CONNS; i++))
 do
     # Start nc in the background to maintain the connection
-    # Redirecting input from /dev/zero or a pipe keeps the connection open
-    nc -v localhost 6379 < /dev/zero > /dev/null 2>&1 &
+    # An idle pipe keeps the connection open; /dev/zero would now be read as
+    # one endless inline command and get the client disconnected
+    sleep infinity | nc -v localhost 6379 > /dev/null 2>&1 &
 
     echo "Started connection $i (PID: $!)"
 done
@@ -0,0 +1,54 @@
+use std::process::Command;
+
+use redis_server::allocator;
+
+/// The allocator the enabled features should have picked
+fn expected() -> &'static str {
+    if cfg!(feature = "jemalloc") {
+        "jemalloc"
+    } else if cfg!(feature = "mimalloc") {
+        "mimalloc"
+    } else {
+        "libc"
+    }
+}
+
+#[test]
+fn reports_the_selected_allocator() {
+    assert_eq!(allocator::NAME, expected());
+    assert_eq!(allocator::stats().is_some(), expected() != "libc");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-redis-server"))
+        .arg("--version")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let version = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        version.contains(&format!(" malloc={} ", expected())),
+        "{}",
+        version
+    );
+}
+
+#[test]
+fn counts_what_this_program_allocates() {
+    let Some(before) = allocator::stats() else {
+        // The system allocator doesn't keep counters
+        assert_eq!(expected(), "libc");
+        return;
+    };
+
+    // Only moves the counters if the reported allocator is the one in use.
+    // Touch every page, or mimalloc need not count them as committed
+    const SIZE: usize = 64 << 20;
+    let block = vec![1u8; SIZE];
+    let after = allocator::stats().unwrap();
+    assert!(
+        after.allocated >= before.allocated + SIZE,
+        "allocated {} -> {}",
+        before.allocated,
+        after.allocated
+    );
+    drop(block);
+}
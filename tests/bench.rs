@@ -0,0 +1,228 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::{rejection, start_server_with, within};
+use redis_server::{
+    bench::{self, BenchConfig, Histogram, Report, Test},
+    command::{Command, CommandContext},
+    reply::ReplyBuilder,
+};
+
+/// `PING`, so the benchmark has one command that succeeds
+struct Ping;
+
+impl Command for Ping {
+    fn name(&self) -> &str {
+        "ping"
+    }
+
+    fn arity(&self) -> i32 {
+        1
+    }
+
+    fn execute(&self, _command: &CommandContext<'_>, reply: &mut ReplyBuilder) {
+        reply.simple("PONG");
+    }
+}
+
+fn parse(args: &[&str]) -> anyhow::Result<BenchConfig> {
+    let config = BenchConfig::from_args(common::args(args))?;
+    Ok(config.expect("not asking for help"))
+}
+
+fn histogram(latencies: impl IntoIterator<Item = Duration>) -> Histogram {
+    let mut histogram = Histogram::default();
+    for latency in latencies {
+        histogram.record(latency);
+    }
+    histogram
+}
+
+/// Latencies of 1µs to `n`µs, all short enough to be recorded exactly
+fn micros(n: u64) -> Histogram {
+    histogram((1..=n).map(Duration::from_micros))
+}
+
+#[test]
+fn parses_flags() {
+    let config = parse(&[]).unwrap();
+    assert_eq!((config.host.as_str(), config.port), ("127.0.0.1", 6379));
+    assert_eq!((config.clients, config.requests), (50, 100_000));
+    assert_eq!((config.pipeline, config.data_size), (1, 3));
+    assert_eq!(config.keyspace, None);
+    assert_eq!(
+        config.tests,
+        [Test::Ping, Test::Set, Test::Get, Test::Incr, Test::Lpush]
+    );
+    assert!(!config.quiet);
+
+    let args = "-h ::1 -p 7000 -c 4 -n 10 -P 3 -d 64 -r 100 -t GET,rpush -q";
+    let config = parse(&args.split(' ').collect::<Vec<_>>()).unwrap();
+    assert_eq!((config.host.as_str(), config.port), ("::1", 7000));
+    assert_eq!((config.clients, config.requests), (4, 10));
+    assert_eq!((config.pipeline, config.data_size), (3, 64));
+    assert_eq!(config.keyspace, Some(100));
+    assert_eq!(config.tests, [Test::Get, Test::Rpush]);
+    assert!(config.quiet);
+}
+
+#[test]
+fn asks_for_help() {
+    let help = |args: &[&str]| BenchConfig::from_args(common::args(args));
+    assert!(help(&["--help"]).unwrap().is_none());
+    assert!(
+        help(&["-c", "4", "--help", "-t", "nope"])
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn rejects_bad_flags() {
+    assert!(rejection(&["-x", "1"], BenchConfig::from_args).starts_with("unknown option '-x'"));
+    assert!(rejection(&["-n"], BenchConfig::from_args).starts_with("missing value for '-n'"));
+    assert!(
+        rejection(&["-t", "ping,del"], BenchConfig::from_args).starts_with("unknown test 'del'")
+    );
+    assert_eq!(
+        rejection(&["-c", "0"], BenchConfig::from_args),
+        "-c and -P must be at least 1"
+    );
+    assert_eq!(
+        rejection(&["-P", "0"], BenchConfig::from_args),
+        "-c and -P must be at least 1"
+    );
+    assert!(parse(&["-p", "70000"]).is_err());
+}
+
+#[test]
+fn percentiles_use_the_nearest_rank() {
+    let latencies = micros(100);
+    assert_eq!(latencies.count(), 100);
+    assert_eq!(latencies.percentile(0.0), Duration::from_micros(1));
+    assert_eq!(latencies.percentile(50.0), Duration::from_micros(50));
+    assert_eq!(latencies.percentile(99.0), Duration::from_micros(99));
+    assert_eq!(latencies.percentile(99.5), Duration::from_micros(100));
+    assert_eq!(latencies.percentile(100.0), Duration::from_micros(100));
+    assert_eq!(latencies.average(), Duration::from_nanos(50_500));
+
+    let latencies = micros(3);
+    assert_eq!(latencies.percentile(50.0), Duration::from_micros(2));
+    assert_eq!(latencies.percentile(95.0), Duration::from_micros(3));
+
+    let empty = Histogram::default();
+    assert_eq!(empty.count(), 0);
+    assert_eq!(empty.percentile(50.0), Duration::ZERO);
+    assert_eq!(empty.average(), Duration::ZERO);
+}
+
+#[test]
+fn histograms_are_accurate_to_within_a_bucket() {
+    let mut micros = 1;
+    while micros < 1 << 36 {
+        let latency = Duration::from_micros(micros);
+        let mut latencies = histogram([Duration::ZERO, latency, Duration::from_secs(1 << 30)]);
+        let reported = latencies.percentile(50.0);
+        assert!(
+            reported <= latency,
+            "{:?} reported as {:?}",
+            latency,
+            reported
+        );
+        assert!(
+            latency - reported <= latency / 256,
+            "{:?} reported as {:?}",
+            latency,
+            reported
+        );
+
+        // The same latency recorded a second time lands in the same bucket
+        latencies.record(latency);
+        assert_eq!(latencies.percentile(75.0), reported);
+        micros = micros * 9 / 8 + 1;
+    }
+
+    // The extremes are kept exactly, whatever bucket they're in
+    let latencies = histogram([
+        Duration::from_nanos(1_234_567),
+        Duration::from_nanos(7_654_321),
+    ]);
+    assert_eq!(latencies.min(), Duration::from_nanos(1_234_567));
+    assert_eq!(latencies.max(), Duration::from_nanos(7_654_321));
+    assert_eq!(latencies.percentile(100.0), Duration::from_nanos(7_654_321));
+    assert_eq!(latencies.average(), Duration::from_nanos(4_444_444));
+}
+
+#[test]
+fn merges_histograms() {
+    let mut latencies = micros(2);
+    latencies.merge(&histogram([Duration::from_micros(7)]));
+    latencies.merge(&Histogram::default());
+    let mut repeated = Histogram::default();
+    repeated.record_n(Duration::from_micros(5), 2);
+    latencies.merge(&repeated);
+
+    assert_eq!(latencies.count(), 5);
+    assert_eq!(latencies.min(), Duration::from_micros(1));
+    assert_eq!(latencies.max(), Duration::from_micros(7));
+    assert_eq!(latencies.percentile(50.0), Duration::from_micros(5));
+    assert_eq!(latencies.average(), Duration::from_micros(4));
+}
+
+#[test]
+fn renders_reports() {
+    let mut config = parse(&["-c", "2", "-d", "8"]).unwrap();
+    let report = Report {
+        elapsed: Duration::from_secs(1),
+        latencies: histogram([100, 200, 300, 400].map(Duration::from_micros)),
+        errors: 0,
+    };
+    assert_eq!(
+        report.render(&config, Test::Set),
+        "\
+====== SET ======
+  4 requests completed in 1.00 seconds
+  2 parallel clients
+  8 bytes payload
+
+Summary:
+  throughput summary: 4.00 requests per second
+  latency summary (msec):
+        avg       min       p50       p95       p99       max
+      0.250     0.100     0.200     0.400     0.400     0.400
+
+"
+    );
+
+    config.pipeline = 16;
+    let report = Report {
+        errors: 3,
+        ..report
+    };
+    let out = report.render(&config, Test::Set);
+    assert!(out.contains("\n  pipeline of 16 requests\n  3 error replies\n"));
+
+    config.quiet = true;
+    assert_eq!(
+        report.render(&config, Test::Get),
+        "GET: 4.00 requests per second, p50=0.200 msec\n"
+    );
+}
+
+#[tokio::test]
+async fn runs_against_a_server() {
+    let server = start_server_with(|builder| builder.command(Ping)).await;
+    let mut config = parse(&["-c", "3", "-n", "25", "-P", "4", "-r", "10"]).unwrap();
+    config.port = server.local_addr().port();
+    let config = Arc::new(config);
+
+    // Every request is answered and timed, even though 25 doesn't split
+    // evenly into pipelines of 4
+    let report = within(bench::run_test(&config, Test::Ping)).await.unwrap();
+    assert_eq!((report.latencies.count(), report.errors), (25, 0));
+
+    // This server has no SET, so every reply is an error
+    let report = within(bench::run_test(&config, Test::Set)).await.unwrap();
+    assert_eq!((report.latencies.count(), report.errors), (25, 25));
+}
@@ -0,0 +1,64 @@
+use redis_server::buffer_pool::BufferPool;
+
+#[test]
+fn reuses_released_buffers() {
+    let pool = BufferPool::new(1024, 4);
+
+    let mut buf = pool.acquire();
+    assert!(buf.capacity() >= 1024);
+    buf.extend_from_slice(b"leftovers");
+    let ptr = buf.as_ptr();
+    drop(buf);
+    assert_eq!(pool.stats().idle, 1);
+
+    // Same allocation, handed back empty
+    let buf = pool.acquire();
+    assert_eq!(buf.as_ptr(), ptr);
+    assert!(buf.is_empty());
+
+    let stats = pool.stats();
+    assert_eq!((stats.hits, stats.misses, stats.idle), (1, 1, 0));
+}
+
+#[test]
+fn drops_buffers_that_grew() {
+    let pool = BufferPool::new(1024, 4);
+
+    let mut buf = pool.acquire();
+    buf.reserve(4096);
+    drop(buf);
+    assert_eq!(pool.stats().idle, 0);
+
+    // Filling a buffer up to its size is fine
+    let mut buf = pool.acquire();
+    buf.resize(1024, 0);
+    drop(buf);
+    assert_eq!(pool.stats().idle, 1);
+}
+
+#[test]
+fn keeps_at_most_max_pooled_buffers() {
+    let pool = BufferPool::new(1024, 2);
+
+    let buffers: Vec<_> = (0..5).map(|_| pool.acquire()).collect();
+    assert_eq!(pool.stats().misses, 5);
+    drop(buffers);
+    assert_eq!(pool.stats().idle, 2);
+
+    let pool = BufferPool::new(1024, 0);
+    drop(pool.acquire());
+    assert_eq!(pool.stats().idle, 0);
+}
+
+#[test]
+fn hit_rate() {
+    let pool = BufferPool::new(1024, 4);
+    assert_eq!(pool.stats().hit_rate(), 0.0);
+
+    drop(pool.acquire());
+    assert_eq!(pool.stats().hit_rate(), 0.0);
+    for _ in 0..3 {
+        drop(pool.acquire());
+    }
+    assert_eq!(pool.stats().hit_rate(), 0.75);
+}
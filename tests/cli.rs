@@ -0,0 +1,181 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::{Echo, rejection, start_server, start_server_with, within};
+use redis_server::{
+    cli::{self, CliConfig, PipeStats},
+    protocol::Frame,
+};
+
+fn parse(args: &[&str]) -> anyhow::Result<CliConfig> {
+    let config = CliConfig::from_args(common::args(args))?;
+    Ok(config.expect("not asking for help"))
+}
+
+fn bulk(data: &str) -> Frame {
+    Frame::Bulk(data.as_bytes().to_vec())
+}
+
+fn render(frame: Frame) -> String {
+    cli::render(&frame, 0)
+}
+
+#[test]
+fn parses_flags() {
+    let config = parse(&[]).unwrap();
+    assert_eq!((config.host.as_str(), config.port), ("127.0.0.1", 6379));
+    assert!(!config.pipe);
+    assert!(config.command.is_empty());
+
+    let config = parse(&["-h", "::1", "-p", "7000", "--pipe"]).unwrap();
+    assert_eq!((config.host.as_str(), config.port), ("::1", 7000));
+    assert!(config.pipe);
+
+    // Everything from the first non-flag on belongs to the command
+    let config = parse(&["-p", "7000", "set", "-p", "--pipe"]).unwrap();
+    assert_eq!(config.port, 7000);
+    assert_eq!(config.command, [&b"set"[..], b"-p", b"--pipe"]);
+    assert!(!config.pipe);
+}
+
+#[test]
+fn asks_for_help() {
+    let help = |args: &[&str]| CliConfig::from_args(common::args(args));
+    assert!(help(&["--help"]).unwrap().is_none());
+    assert!(help(&["-p", "7000", "--help", "get"]).unwrap().is_none());
+
+    // After the command name it's just another argument
+    let config = help(&["get", "--help"]).unwrap().unwrap();
+    assert_eq!(config.command, [&b"get"[..], b"--help"]);
+}
+
+#[test]
+fn rejects_bad_flags() {
+    assert!(rejection(&["-x"], CliConfig::from_args).starts_with("unknown option '-x'"));
+    assert!(rejection(&["-p"], CliConfig::from_args).starts_with("missing value for '-p'"));
+    assert!(parse(&["-p", "http"]).is_err());
+}
+
+#[test]
+fn quotes_bulk_strings() {
+    assert_eq!(cli::quote(b""), r#""""#);
+    assert_eq!(cli::quote(b"hello world"), r#""hello world""#);
+    assert_eq!(
+        cli::quote(b"\"a\\b\"\n\r\t\x07\x08"),
+        r#""\"a\\b\"\n\r\t\a\b""#
+    );
+    assert_eq!(cli::quote(b"\x00\x7f\xff"), r#""\x00\x7f\xff""#);
+}
+
+#[test]
+fn renders_scalars() {
+    assert_eq!(render(Frame::Simple("OK".into())), "OK\n");
+    assert_eq!(render(Frame::Error("ERR no".into())), "(error) ERR no\n");
+    assert_eq!(render(Frame::Integer(-3)), "(integer) -3\n");
+    assert_eq!(render(bulk("a\"b")), "\"a\\\"b\"\n");
+    assert_eq!(render(Frame::Null), "(nil)\n");
+    assert_eq!(render(Frame::Double(1.5)), "(double) 1.5\n");
+    assert_eq!(render(Frame::Double(f64::INFINITY)), "(double) inf\n");
+    assert_eq!(render(Frame::Boolean(false)), "(false)\n");
+    assert_eq!(
+        render(Frame::BigNumber("12345678901234567890".into())),
+        "(big number) 12345678901234567890\n"
+    );
+    assert_eq!(
+        render(Frame::Verbatim {
+            format: "txt".into(),
+            text: b"some text".to_vec(),
+        }),
+        "some text\n"
+    );
+    assert_eq!(
+        render(Frame::Attribute {
+            attributes: vec![(bulk("ttl"), Frame::Integer(3))],
+            value: Box::new(Frame::Integer(1)),
+        }),
+        "(integer) 1\n"
+    );
+}
+
+#[test]
+fn renders_aggregates() {
+    assert_eq!(render(Frame::Array(vec![])), "(empty array)\n");
+    assert_eq!(render(Frame::Set(vec![])), "(empty set)\n");
+    assert_eq!(render(Frame::Map(vec![])), "(empty hash)\n");
+
+    assert_eq!(
+        render(Frame::Array(vec![
+            bulk("a"),
+            Frame::Array(vec![Frame::Integer(1), bulk("b")]),
+            Frame::Null,
+        ])),
+        "1) \"a\"\n2) 1) (integer) 1\n   2) \"b\"\n3) (nil)\n"
+    );
+    assert_eq!(
+        render(Frame::Push(vec![bulk("message"), bulk("news")])),
+        "1) \"message\"\n2) \"news\"\n"
+    );
+    assert_eq!(
+        render(Frame::Set(vec![bulk("x"), bulk("y")])),
+        "1~ \"x\"\n2~ \"y\"\n"
+    );
+    assert_eq!(
+        render(Frame::Map(vec![
+            (bulk("k"), Frame::Integer(1)),
+            (bulk("j"), bulk("v")),
+        ])),
+        "1# \"k\" => (integer) 1\n2# \"j\" => \"v\"\n"
+    );
+
+    // Positions are right-aligned, and nested elements line up with them
+    let items = (0..10).map(|i| bulk(&i.to_string())).collect();
+    let out = render(Frame::Array(vec![Frame::Array(items)]));
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines[0], "1)  1) \"0\"");
+    assert_eq!(lines[1], "    2) \"1\"");
+    assert_eq!(lines[9], "   10) \"9\"");
+}
+
+#[tokio::test]
+async fn pipes_input_to_the_server() {
+    let server = start_server_with(|builder| builder.command(Echo)).await;
+    let addr = server.local_addr().to_string();
+    let input = b"*2\r\n$4\r\nECHO\r\n$1\r\na\r\nnope\r\necho b\r\n".to_vec();
+
+    let stats = within(tokio::task::spawn_blocking(move || {
+        cli::pipe(&addr, Cursor::new(input))
+    }))
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        stats,
+        PipeStats {
+            replies: 3,
+            errors: 1
+        }
+    );
+}
+
+#[tokio::test]
+async fn pipes_to_a_server_without_echo() {
+    let server = start_server().await;
+    let addr = server.local_addr().to_string();
+    let input = b"PING\r\nPING\r\n".to_vec();
+
+    // The marker's own unknown command error isn't one of the input's
+    let stats = within(tokio::task::spawn_blocking(move || {
+        cli::pipe(&addr, Cursor::new(input))
+    }))
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        stats,
+        PipeStats {
+            replies: 2,
+            errors: 2
+        }
+    );
+}
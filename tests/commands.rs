@@ -0,0 +1,146 @@
+mod common;
+
+use common::{Echo, connect, read_reply, read_to_close, start_server_with};
+use redis_server::{
+    Server, ServerConfig,
+    command::{Command, CommandContext},
+    protocol::Frame,
+    reply::ReplyBuilder,
+};
+use tokio::io::AsyncWriteExt;
+
+/// `COUNT arg [arg ...]`, replying with the number of arguments
+struct Count;
+
+impl Command for Count {
+    fn name(&self) -> &str {
+        "Count"
+    }
+
+    fn arity(&self) -> i32 {
+        -2
+    }
+
+    fn execute(&self, command: &CommandContext<'_>, reply: &mut ReplyBuilder) {
+        reply.integer(command.args.len() as i64 - 1);
+    }
+}
+
+/// Starts a reply, then panics halfway through it
+struct Broken;
+
+impl Command for Broken {
+    fn name(&self) -> &str {
+        "broken"
+    }
+
+    fn arity(&self) -> i32 {
+        1
+    }
+
+    fn execute(&self, _command: &CommandContext<'_>, reply: &mut ReplyBuilder) {
+        reply.array(2);
+        reply.integer(1);
+        panic!("broken command");
+    }
+}
+
+fn wrong_arity(command: &str) -> Frame {
+    Frame::Error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        command
+    ))
+}
+
+#[tokio::test]
+async fn runs_added_commands() {
+    let server = start_server_with(|builder| builder.command(Echo).command(Count)).await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    socket
+        .write_all(b"echo hello\r\nECHO again\r\ncount a b c\r\nCOUNT a\r\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        read_reply(&mut socket, &mut buf).await,
+        Frame::Bulk(b"hello".to_vec())
+    );
+    assert_eq!(
+        read_reply(&mut socket, &mut buf).await,
+        Frame::Bulk(b"again".to_vec())
+    );
+    assert_eq!(read_reply(&mut socket, &mut buf).await, Frame::Integer(3));
+    assert_eq!(read_reply(&mut socket, &mut buf).await, Frame::Integer(1));
+}
+
+#[tokio::test]
+async fn checks_arity_before_running() {
+    let server = start_server_with(|builder| builder.command(Echo).command(Count)).await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    socket
+        .write_all(b"echo\r\nEcho a b\r\ncount\r\n")
+        .await
+        .unwrap();
+    assert_eq!(read_reply(&mut socket, &mut buf).await, wrong_arity("echo"));
+    assert_eq!(read_reply(&mut socket, &mut buf).await, wrong_arity("echo"));
+    assert_eq!(
+        read_reply(&mut socket, &mut buf).await,
+        wrong_arity("count")
+    );
+}
+
+#[tokio::test]
+async fn panicking_commands_drop_their_partial_reply() {
+    let server = start_server_with(|builder| builder.command(Broken)).await;
+    let mut socket = connect(server.local_addr()).await;
+
+    socket.write_all(b"broken\r\n").await.unwrap();
+    assert_eq!(read_to_close(&mut socket).await, b"-ERR internal error\r\n");
+    assert_eq!(server.server().stats().internal_errors, 1);
+}
+
+#[tokio::test]
+async fn renames_and_disables_commands() {
+    // Given the way redis-server takes them on its command line
+    let args = [
+        "--rename-command",
+        "ECHO",
+        "shout",
+        "--rename-command",
+        "count",
+        "",
+        "--rename-command",
+        "flushall",
+        "",
+    ];
+    let config = ServerConfig::from_args(args.map(String::from)).unwrap();
+
+    let server =
+        start_server_with(|builder| builder.config(config).command(Echo).command(Count)).await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    socket
+        .write_all(b"shout hi\r\necho hi\r\ncount a\r\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        read_reply(&mut socket, &mut buf).await,
+        Frame::Bulk(b"hi".to_vec())
+    );
+    for command in ["echo", "count"] {
+        assert!(matches!(
+            read_reply(&mut socket, &mut buf).await,
+            Frame::Error(e) if e.starts_with(&format!("ERR unknown command '{}'", command))
+        ));
+    }
+}
+
+#[test]
+#[should_panic(expected = "command 'echo' added twice")]
+fn names_must_be_unique() {
+    Server::builder().command(Echo).command(Echo);
+}
@@ -0,0 +1,120 @@
+//! Helpers shared by the integration tests
+//!
+//! Every test gets its own server on an ephemeral port, so tests can run in
+//! parallel without stepping on each other (or on a real Redis on 6379).
+
+#![allow(dead_code)]
+
+use std::{future::Future, net::SocketAddr, time::Duration};
+
+use redis_server::{
+    Server, ServerBuilder, ServerConfig, ServerHandle,
+    command::{Command, CommandContext},
+    protocol::{self, Frame},
+    reply::ReplyBuilder,
+};
+use tokio::{io::AsyncReadExt, net::TcpStream};
+
+/// Upper bound for anything a test waits on, so a hang fails instead of stalling CI
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `ECHO message`
+pub struct Echo;
+
+impl Command for Echo {
+    fn name(&self) -> &str {
+        "echo"
+    }
+
+    fn arity(&self) -> i32 {
+        2
+    }
+
+    fn execute(&self, command: &CommandContext<'_>, reply: &mut ReplyBuilder) {
+        reply.bulk(&command.args[1]);
+    }
+}
+
+/// Start a server with the defaults on an ephemeral port
+pub async fn start_server() -> ServerHandle {
+    start_server_with(|builder| builder).await
+}
+
+/// Start a server on an ephemeral port after adjusting the builder
+pub async fn start_server_with(
+    configure: impl FnOnce(ServerBuilder) -> ServerBuilder,
+) -> ServerHandle {
+    configure(Server::builder().port(0))
+        .spawn()
+        .await
+        .expect("server should start")
+}
+
+/// Start a server with `config` on an ephemeral port, whatever port it names
+pub async fn start_server_with_config(config: ServerConfig) -> ServerHandle {
+    start_server_with(|builder| builder.config(config)).await
+}
+
+/// Open a raw TCP connection to the server
+pub async fn connect(addr: SocketAddr) -> TcpStream {
+    within(TcpStream::connect(addr))
+        .await
+        .expect("should be able to connect")
+}
+
+/// Await `future`, failing the test if it takes longer than [`TIMEOUT`]
+pub async fn within<F: Future>(future: F) -> F::Output {
+    tokio::time::timeout(TIMEOUT, future)
+        .await
+        .expect("timed out")
+}
+
+/// Poll `condition` until it holds, failing the test after [`TIMEOUT`]
+///
+/// Accepting a connection and counting it happen on the server's tasks, so
+/// a test can't assume the server has caught up the moment `connect` returns.
+pub async fn eventually(mut condition: impl FnMut() -> bool) {
+    within(async {
+        while !condition() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+}
+
+/// Read one RESP reply, failing the test if the server closes first
+pub async fn read_reply(socket: &mut TcpStream, buf: &mut Vec<u8>) -> Frame {
+    within(async {
+        loop {
+            if let Some((frame, len)) = protocol::parse_frame(buf).expect("valid reply") {
+                buf.drain(..len);
+                return frame;
+            }
+            let n = socket.read_buf(buf).await.expect("read should succeed");
+            assert!(n > 0, "server closed the connection before replying");
+        }
+    })
+    .await
+}
+
+/// Read until the server closes the connection, returning everything it sent
+pub async fn read_to_close(socket: &mut TcpStream) -> Vec<u8> {
+    let mut out = Vec::new();
+    within(socket.read_to_end(&mut out))
+        .await
+        .expect("read should succeed");
+    out
+}
+
+/// Command-line arguments, owned like the ones from `std::env::args`
+pub fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|&arg| arg.to_owned()).collect()
+}
+
+/// The error `parse` rejects `args` with, failing the test if it doesn't
+pub fn rejection<T>(args: &[&str], parse: impl FnOnce(Vec<String>) -> anyhow::Result<T>) -> String {
+    match parse(self::args(args)) {
+        Ok(_) => panic!("{:?} should be rejected", args),
+        Err(e) => e.to_string(),
+    }
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::{args, rejection};
+use redis_server::ServerConfig;
+
+#[test]
+fn applies_options_by_their_redis_names() {
+    let config = ServerConfig::from_args(args(&[
+        "--port",
+        "7000",
+        "--maxclients",
+        "10",
+        "--proto-max-bulk-len",
+        "1mb",
+        "--metrics-port",
+        "0",
+    ]))
+    .unwrap();
+
+    assert_eq!(config.port, 7000);
+    assert_eq!(config.max_connections, 10);
+    assert_eq!(config.proto_max_bulk_len, 1024 * 1024);
+    assert_eq!(config.metrics_port, None);
+}
+
+#[test]
+fn options_take_every_argument_up_to_the_next_option() {
+    let config = ServerConfig::from_args(args(&[
+        "--rename-command",
+        "flushall",
+        "",
+        "--port",
+        "7000",
+        "--rename-command",
+        "CONFIG",
+        "my \"config\"",
+    ]))
+    .unwrap();
+    assert_eq!(
+        config.renamed_commands,
+        [
+            ("flushall".to_owned(), String::new()),
+            ("config".to_owned(), "my \"config\"".to_owned())
+        ]
+    );
+    assert_eq!(config.port, 7000);
+
+    assert_eq!(
+        rejection(
+            &["--rename-command", "flushall", "a", "b"],
+            ServerConfig::from_args
+        ),
+        "can't apply option '--rename-command \"flushall\" \"a\" \"b\"': \
+         expected a command name and its new name, e.g. 'flushall \"\"'"
+    );
+    assert_eq!(
+        rejection(&["--port", "1", "2"], ServerConfig::from_args),
+        "can't apply option '--port \"1\" \"2\"': invalid digit found in string"
+    );
+}
+
+#[test]
+fn reports_every_problem_at_once() {
+    let error = rejection(
+        &[
+            "stray",
+            "--port",
+            "seven",
+            "--nope",
+            "1",
+            "--maxclients",
+            "0",
+            "--loglevel",
+        ],
+        ServerConfig::from_args,
+    );
+
+    assert_eq!(
+        error,
+        [
+            "5 problems with the configuration:",
+            "  - unexpected argument 'stray', options look like '--port 6379'",
+            "  - can't apply option '--port seven': invalid digit found in string",
+            "  - can't apply option '--nope 1': unknown option 'nope'",
+            "  - missing value for option '--loglevel'",
+            // Checked once all the arguments are in
+            "  - maxclients must be at least 1",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn checks_options_against_each_other() {
+    let error = rejection(
+        &["--port", "7000", "--metrics-port", "7000"],
+        ServerConfig::from_args,
+    );
+    assert_eq!(error, "metrics-port 7000 is also the client port");
+
+    let error = rejection(
+        &["--client-query-buffer-limit", "1mb"],
+        ServerConfig::from_args,
+    );
+    assert!(error.starts_with("client-query-buffer-limit (1048576) is below proto-max-bulk-len"));
+
+    // Fine once the bulk limit comes down too
+    ServerConfig::from_args(args(&[
+        "--client-query-buffer-limit",
+        "1mb",
+        "--proto-max-bulk-len",
+        "1mb",
+    ]))
+    .unwrap();
+}
@@ -0,0 +1,307 @@
+mod common;
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use common::{
+    connect, eventually, read_reply, start_server, start_server_with, start_server_with_config,
+    within,
+};
+use redis_server::{
+    Server, ServerConfig,
+    command::{Command, CommandContext},
+    reply::ReplyBuilder,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+#[tokio::test]
+async fn binds_an_ephemeral_port() {
+    let server = start_server().await;
+
+    assert_ne!(server.local_addr().port(), 0);
+    assert!(server.local_addr().ip().is_loopback());
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn counts_concurrent_clients() {
+    let server = start_server().await;
+
+    let mut clients = Vec::new();
+    for _ in 0..10 {
+        clients.push(connect(server.local_addr()).await);
+    }
+
+    let stats = || server.server().stats();
+    eventually(|| stats().connected_clients == 10).await;
+    assert_eq!(stats().total_connections_received, 10);
+    assert_eq!(stats().rejected_connections, 0);
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn builder_settings_outlast_a_later_config() {
+    // Binding the config's port would fail, since something already has it
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = ServerConfig {
+        port: taken.local_addr().unwrap().port(),
+        max_connections: 100,
+        ..ServerConfig::default()
+    };
+    let server = Server::builder()
+        .port(0)
+        .max_connections(1)
+        .config(config)
+        .spawn()
+        .await
+        .expect("server should start on an ephemeral port");
+
+    let _first = connect(server.local_addr()).await;
+    eventually(|| server.server().stats().connected_clients == 1).await;
+    let mut second = connect(server.local_addr()).await;
+    let mut reply = String::new();
+    within(second.read_to_string(&mut reply)).await.unwrap();
+    assert_eq!(reply, "-ERR max number of clients reached\r\n");
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn rejects_clients_over_max_connections() {
+    let server = start_server_with(|builder| builder.max_connections(2)).await;
+
+    let _first = connect(server.local_addr()).await;
+    let _second = connect(server.local_addr()).await;
+    eventually(|| server.server().stats().connected_clients == 2).await;
+
+    let mut third = connect(server.local_addr()).await;
+    let mut reply = String::new();
+    within(third.read_to_string(&mut reply)).await.unwrap();
+    assert_eq!(reply, "-ERR max number of clients reached\r\n");
+
+    let stats = server.server().stats();
+    assert_eq!(stats.connected_clients, 2);
+    assert_eq!(stats.rejected_connections, 1);
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn rejects_clients_over_the_connection_rate() {
+    let config = ServerConfig {
+        per_ip_connection_rate: 2,
+        ..ServerConfig::default()
+    };
+    let server = start_server_with_config(config).await;
+
+    let _first = connect(server.local_addr()).await;
+    let _second = connect(server.local_addr()).await;
+
+    let mut third = connect(server.local_addr()).await;
+    let mut reply = String::new();
+    within(third.read_to_string(&mut reply)).await.unwrap();
+    assert_eq!(reply, "-ERR max connection rate per IP reached\r\n");
+
+    let stats = server.server().stats();
+    assert_eq!(stats.throttled_connections, 1);
+    assert_eq!(stats.rejected_connections, 0);
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn slows_down_clients_over_the_command_rate() {
+    let config = ServerConfig {
+        per_ip_command_rate: 10,
+        ..ServerConfig::default()
+    };
+    let server = start_server_with_config(config).await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    // A burst's worth goes through straight away, the rest at the rate
+    let start = Instant::now();
+    socket.write_all(&b"PING\r\n".repeat(20)).await.unwrap();
+    for _ in 0..10 {
+        read_reply(&mut socket, &mut buf).await;
+    }
+    assert!(start.elapsed() < Duration::from_millis(500));
+    for _ in 0..10 {
+        read_reply(&mut socket, &mut buf).await;
+    }
+    assert!(start.elapsed() >= Duration::from_millis(800));
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn pipelining_does_not_run_up_a_debt() {
+    let config = ServerConfig {
+        per_ip_command_rate: 10,
+        ..ServerConfig::default()
+    };
+    let server = start_server_with_config(config).await;
+    let mut greedy = connect(server.local_addr()).await;
+    let mut other = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    // Ten seconds' worth of commands from one client...
+    greedy.write_all(&b"PING\r\n".repeat(100)).await.unwrap();
+    read_reply(&mut greedy, &mut buf).await;
+
+    // ...only hold another client from the same IP up for its turns
+    let mut buf = Vec::new();
+    let start = Instant::now();
+    for _ in 0..2 {
+        other.write_all(b"PING\r\n").await.unwrap();
+        read_reply(&mut other, &mut buf).await;
+    }
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn stops_accepting_after_shutdown() {
+    let server = start_server().await;
+    let addr = server.local_addr();
+
+    server.shutdown().await.unwrap();
+
+    assert!(TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn disconnects_clients_on_shutdown() {
+    let server = start_server().await;
+    let mut idle = connect(server.local_addr()).await;
+    let mut busy = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    busy.write_all(b"one\r\n").await.unwrap();
+    read_reply(&mut busy, &mut buf).await;
+    eventually(|| server.server().stats().connected_clients == 2).await;
+
+    within(server.shutdown()).await.unwrap();
+
+    // Both were told to go before shutdown returned
+    let mut rest = Vec::new();
+    assert_eq!(within(idle.read_to_end(&mut rest)).await.unwrap(), 0);
+    assert_eq!(within(busy.read_to_end(&mut rest)).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn checks_out_reply_buffers_only_for_replies() {
+    let server = start_server().await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    socket.write_all(b"one\r\n").await.unwrap();
+    read_reply(&mut socket, &mut buf).await;
+    drop(socket);
+    eventually(|| server.server().stats().connected_clients == 0).await;
+
+    // The read buffer, and one for the reply
+    let stats = server.server().stats().buffer_pool;
+    assert_eq!((stats.hits + stats.misses, stats.idle), (2, 2));
+}
+
+#[tokio::test]
+async fn read_buffers_stay_poolable() {
+    let server = start_server().await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    // Each request fits in a read buffer, but the server reads most of one
+    // before the rest arrives, leaving little room for it
+    let arg = "x".repeat(13 * 1024);
+    let request = format!("*2\r\n$4\r\nnope\r\n${}\r\n{}\r\n", arg.len(), arg);
+    let (start, rest) = request.as_bytes().split_at(12 * 1024 + 512);
+    for _ in 0..3 {
+        socket.write_all(start).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        socket.write_all(rest).await.unwrap();
+        read_reply(&mut socket, &mut buf).await;
+    }
+    drop(socket);
+    eventually(|| server.server().stats().connected_clients == 0).await;
+
+    // Nothing grew past the buffer size, so everything went back to the pool
+    let stats = server.server().stats().buffer_pool;
+    assert_eq!(stats.idle, stats.misses);
+}
+
+/// `BIG`, a reply far too large to sit in socket buffers unread
+struct Big;
+
+impl Command for Big {
+    fn name(&self) -> &str {
+        "big"
+    }
+
+    fn arity(&self) -> i32 {
+        1
+    }
+
+    fn execute(&self, _command: &CommandContext<'_>, reply: &mut ReplyBuilder) {
+        reply.bulk(&vec![b'x'; 1024 * 1024]);
+    }
+}
+
+#[tokio::test]
+async fn shutdown_timeout_disconnects_clients_that_stop_reading() {
+    let server = start_server_with(|builder| builder.command(Big)).await;
+    let stats = Arc::clone(server.server());
+    let mut socket = connect(server.local_addr()).await;
+
+    // Only read the start of the first reply, so the rest back up until the
+    // server can't write any more
+    socket.write_all(&b"big\r\n".repeat(32)).await.unwrap();
+    let mut start = [0; 16];
+    within(socket.read_exact(&mut start)).await.unwrap();
+
+    within(server.shutdown_timeout(Duration::from_millis(100)))
+        .await
+        .unwrap();
+    assert_eq!(stats.stats().connected_clients, 0);
+}
+
+#[tokio::test]
+async fn shutdown_timeout_waits_for_clients_that_finish() {
+    let server = start_server().await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    socket.write_all(b"one\r\n").await.unwrap();
+    read_reply(&mut socket, &mut buf).await;
+
+    within(server.shutdown_timeout(common::TIMEOUT))
+        .await
+        .unwrap();
+    let mut rest = Vec::new();
+    assert_eq!(within(socket.read_to_end(&mut rest)).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn shutdown_does_not_wait_for_throttled_clients() {
+    let config = ServerConfig {
+        per_ip_command_rate: 1,
+        ..ServerConfig::default()
+    };
+    let server = start_server_with_config(config).await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    // Enough to keep the client throttled for far longer than the test waits
+    socket.write_all(&b"nope\r\n".repeat(100)).await.unwrap();
+    read_reply(&mut socket, &mut buf).await;
+
+    within(server.shutdown()).await.unwrap();
+}
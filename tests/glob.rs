@@ -0,0 +1,96 @@
+use redis_server::glob::string_match;
+
+fn matches(pattern: &str, string: &str) -> bool {
+    string_match(pattern.as_bytes(), string.as_bytes(), false)
+}
+
+#[test]
+fn wildcards() {
+    assert!(matches("h?llo", "hello"));
+    assert!(matches("h?llo", "hxllo"));
+    assert!(!matches("h?llo", "hllo"));
+
+    assert!(matches("h*llo", "hllo"));
+    assert!(matches("h*llo", "heeeello"));
+    assert!(matches("*", "anything"));
+    assert!(matches("a*", "a"));
+    assert!(matches("a**b", "ab"));
+    assert!(matches("*o*", "foo"));
+    assert!(!matches("h*llo", "hello!"));
+
+    assert!(matches("", ""));
+    assert!(!matches("", "a"));
+    assert!(!matches("?", ""));
+}
+
+#[test]
+fn a_lone_star_does_not_match_the_empty_string() {
+    // Same as Redis: `KEYS *` never returns the empty key
+    assert!(!matches("*", ""));
+    assert!(!matches("**", ""));
+}
+
+#[test]
+fn sets_and_ranges() {
+    assert!(matches("h[ae]llo", "hello"));
+    assert!(matches("h[ae]llo", "hallo"));
+    assert!(!matches("h[ae]llo", "hillo"));
+
+    assert!(matches("h[^e]llo", "hallo"));
+    assert!(!matches("h[^e]llo", "hello"));
+
+    assert!(matches("h[a-b]llo", "hbllo"));
+    assert!(!matches("h[a-b]llo", "hcllo"));
+    assert!(matches("h[b-a]llo", "hallo"));
+    assert!(matches("[a-]", "]"));
+    assert!(matches("[0-9a-f]*", "7up"));
+    assert!(!matches("[^0-9]*", "7up"));
+
+    // An unterminated set runs to the end of the pattern
+    assert!(matches("[abc", "b"));
+    assert!(!matches("a[", "ab"));
+}
+
+#[test]
+fn escapes() {
+    assert!(matches("h\\*llo", "h*llo"));
+    assert!(!matches("h\\*llo", "hello"));
+    assert!(matches("\\?", "?"));
+    assert!(!matches("\\?", "x"));
+    assert!(matches("[\\]]", "]"));
+    assert!(matches("[\\^a]", "^"));
+    assert!(matches("{a}*", "{a}:1"));
+
+    // A trailing backslash is just a backslash
+    assert!(matches("a\\", "a\\"));
+}
+
+#[test]
+fn nocase() {
+    assert!(string_match(b"HELLO", b"hello", true));
+    assert!(string_match(b"h[A-C]llo", b"hbllo", true));
+    assert!(string_match(b"[^X]", b"y", true));
+    assert!(!string_match(b"[^X]", b"x", true));
+    assert!(!string_match(b"HELLO", b"hello", false));
+
+    // Escaped set members are compared exactly
+    assert!(!string_match(b"[\\A]", b"a", true));
+}
+
+#[test]
+fn binary_safe() {
+    assert!(string_match(b"a\0*", b"a\0\xff", false));
+    assert!(string_match(b"[\x80-\xff]", b"\x90", false));
+    assert!(!string_match(b"[^\x80-\xff]", b"\x90", false));
+}
+
+#[test]
+fn gives_up_early_on_pathological_patterns() {
+    // Regression tests from Redis' keyspace suite: both would take forever
+    // with naive backtracking
+    let pattern = format!("{}b", "a*".repeat(45));
+    assert!(!matches(&pattern, &"a".repeat(100)));
+
+    let pattern = "*?".repeat(50_000);
+    assert!(!matches(&pattern, &"a".repeat(50_000)));
+}
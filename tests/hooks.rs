@@ -0,0 +1,94 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{connect, eventually, read_reply, read_to_close, start_server_with};
+use redis_server::{
+    hooks::{CommandContext, CommandHook},
+    protocol::Frame,
+};
+use tokio::io::AsyncWriteExt;
+
+/// Records what it sees as `before NAME` and `after NAME REPLY` lines
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<String>>>);
+
+impl Recorder {
+    fn events(&self) -> Vec<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl CommandHook for Recorder {
+    fn before(&self, command: &CommandContext<'_>) {
+        let name = String::from_utf8_lossy(command.name());
+        self.0.lock().unwrap().push(format!("before {}", name));
+    }
+
+    fn after(&self, command: &CommandContext<'_>, reply: &[u8]) {
+        let name = String::from_utf8_lossy(command.name());
+        let reply = String::from_utf8_lossy(reply);
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("after {} {}", name, reply.trim_end()));
+    }
+}
+
+struct Explode;
+
+impl CommandHook for Explode {
+    fn before(&self, command: &CommandContext<'_>) {
+        if command.name() == b"boom" {
+            panic!("hook exploded");
+        }
+    }
+}
+
+#[tokio::test]
+async fn hooks_run_around_each_command() {
+    let recorder = Recorder::default();
+    let server = start_server_with(|builder| builder.hook(recorder.clone())).await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    socket.write_all(b"one\r\ntwo x\r\n").await.unwrap();
+    read_reply(&mut socket, &mut buf).await;
+    read_reply(&mut socket, &mut buf).await;
+
+    assert_eq!(
+        recorder.events(),
+        [
+            "before one",
+            "after one -ERR unknown command 'one', with args beginning with:",
+            "before two",
+            "after two -ERR unknown command 'two', with args beginning with: 'x'",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn panicking_hooks_are_internal_errors() {
+    let server = start_server_with(|builder| builder.hook(Explode)).await;
+    let mut socket = connect(server.local_addr()).await;
+
+    socket
+        .write_all(b"fine\r\nboom\r\nnever\r\n")
+        .await
+        .unwrap();
+    let out = read_to_close(&mut socket).await;
+    assert!(out.starts_with(b"-ERR unknown command 'fine'"));
+    assert!(out.ends_with(b"-ERR internal error\r\n"));
+    assert!(!String::from_utf8_lossy(&out).contains("never"));
+
+    eventually(|| server.server().stats().internal_errors == 1).await;
+
+    // Other clients are unaffected
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+    socket.write_all(b"fine\r\n").await.unwrap();
+    assert!(matches!(
+        read_reply(&mut socket, &mut buf).await,
+        Frame::Error(e) if e.starts_with("ERR unknown command")
+    ));
+}
@@ -0,0 +1,66 @@
+use redis_server::index::{bytes, element, ranks};
+
+fn lrange<'a>(list: &[&'a str], start: i64, end: i64) -> Vec<&'a str> {
+    list[ranks(start, end, list.len())].to_vec()
+}
+
+fn getrange(string: &str, start: i64, end: i64) -> &str {
+    &string[bytes(start, end, string.len())]
+}
+
+#[test]
+fn element_indexes() {
+    assert_eq!(element(0, 3), Some(0));
+    assert_eq!(element(2, 3), Some(2));
+    assert_eq!(element(-1, 3), Some(2));
+    assert_eq!(element(-3, 3), Some(0));
+
+    assert_eq!(element(3, 3), None);
+    assert_eq!(element(-4, 3), None);
+    assert_eq!(element(0, 0), None);
+    assert_eq!(element(i64::MIN, 3), None);
+    assert_eq!(element(i64::MAX, 3), None);
+}
+
+#[test]
+fn rank_ranges_match_lrange() {
+    // The examples from the LRANGE docs
+    let list = ["one", "two", "three"];
+    assert_eq!(lrange(&list, 0, 0), ["one"]);
+    assert_eq!(lrange(&list, -3, 2), ["one", "two", "three"]);
+    assert_eq!(lrange(&list, -100, 100), ["one", "two", "three"]);
+    assert!(lrange(&list, 5, 10).is_empty());
+
+    assert_eq!(lrange(&list, 0, -1), ["one", "two", "three"]);
+    assert_eq!(lrange(&list, -2, -1), ["two", "three"]);
+    assert_eq!(lrange(&list, 1, 1), ["two"]);
+    assert!(lrange(&list, 2, 1).is_empty());
+    assert!(lrange(&list, -1, -2).is_empty());
+    assert!(lrange(&list, 0, -100).is_empty());
+    assert!(lrange(&[], 0, -1).is_empty());
+
+    assert_eq!(ranks(i64::MIN, i64::MAX, 3), 0..3);
+}
+
+#[test]
+fn byte_ranges_match_getrange() {
+    // The examples from the GETRANGE docs
+    let string = "This is a string";
+    assert_eq!(getrange(string, 0, 3), "This");
+    assert_eq!(getrange(string, -3, -1), "ing");
+    assert_eq!(getrange(string, 0, -1), string);
+    assert_eq!(getrange(string, 10, 100), "string");
+
+    assert_eq!(getrange(string, 5, 3), "");
+    assert_eq!(getrange(string, -1, -5), "");
+    assert_eq!(getrange(string, 100, 200), "");
+    assert_eq!(getrange("", 0, -1), "");
+
+    // Unlike LRANGE, an end before the start of the string is clamped to
+    // the first byte
+    assert_eq!(getrange(string, 0, -100), "T");
+    assert_eq!(getrange(string, -100, -90), "T");
+    assert!(lrange(&["T"], 0, -100).is_empty());
+
+    assert_eq!(bytes(i64::MIN, i64::MAX, 3), 0..3);
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use common::{connect, eventually, read_to_close, start_server, start_server_with_config};
+use redis_server::{ServerConfig, ServerHandle};
+use tokio::io::AsyncWriteExt;
+
+/// Send a raw HTTP request and return the status line and body
+async fn request(addr: SocketAddr, request: &str) -> (String, String) {
+    let mut socket = connect(addr).await;
+    socket.write_all(request.as_bytes()).await.unwrap();
+    let response = String::from_utf8(read_to_close(&mut socket).await).unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").expect("complete response");
+    let status = head.lines().next().unwrap().to_owned();
+    (status, body.to_owned())
+}
+
+async fn start_with_metrics() -> ServerHandle {
+    let config = ServerConfig {
+        metrics_port: Some(0),
+        ..ServerConfig::default()
+    };
+    start_server_with_config(config).await
+}
+
+#[tokio::test]
+async fn metrics_are_off_by_default() {
+    let server = start_server().await;
+    assert_eq!(server.metrics_addr(), None);
+}
+
+#[tokio::test]
+async fn serves_metrics() {
+    let server = start_with_metrics().await;
+    let addr = server.metrics_addr().expect("metrics enabled");
+    assert_ne!(addr.port(), server.local_addr().port());
+
+    let _client = connect(server.local_addr()).await;
+    eventually(|| server.server().stats().connected_clients == 1).await;
+
+    let (status, body) = request(addr, "GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(body.contains("# TYPE redis_connected_clients gauge\nredis_connected_clients 1\n"));
+    for name in [
+        "redis_connections_received_total",
+        "redis_rejected_connections_total",
+        "redis_overflowed_connections_total",
+        "redis_throttled_connections_total",
+        "redis_internal_errors_total",
+        "redis_buffer_pool_hits_total",
+        "redis_buffer_pool_misses_total",
+        "redis_buffer_pool_idle",
+        "redis_task_restarts_total{task=\"metrics\"}",
+    ] {
+        assert!(
+            body.lines()
+                .any(|line| line.starts_with(&format!("{} ", name))),
+            "{} missing from:\n{}",
+            name,
+            body
+        );
+    }
+}
+
+#[tokio::test]
+async fn rejects_other_paths_and_methods() {
+    let server = start_with_metrics().await;
+    let addr = server.metrics_addr().unwrap();
+
+    let (status, _) = request(addr, "GET / HTTP/1.1\r\n\r\n").await;
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+
+    let (status, _) = request(addr, "POST /metrics HTTP/1.1\r\n\r\n").await;
+    assert_eq!(status, "HTTP/1.1 405 Method Not Allowed");
+}
@@ -0,0 +1,377 @@
+mod common;
+
+use common::{
+    connect, eventually, read_reply, read_to_close, start_server, start_server_with_config, within,
+};
+use redis_server::{
+    ServerConfig,
+    protocol::{Frame, RequestLimits, RequestParser, split_args},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+fn unknown(command: &str, args: &str) -> Frame {
+    Frame::Error(format!(
+        "ERR unknown command '{}', with args beginning with: {}",
+        command, args
+    ))
+}
+
+#[tokio::test]
+async fn answers_multibulk_requests() {
+    let server = start_server().await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    socket
+        .write_all(b"*2\r\n$4\r\nNOPE\r\n$3\r\nfoo\r\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        read_reply(&mut socket, &mut buf).await,
+        unknown("NOPE", "'foo' ")
+    );
+}
+
+#[tokio::test]
+async fn answers_inline_requests() {
+    let server = start_server().await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    socket
+        .write_all(b"nope \"a b\" 'c'\r\n\r\nnope\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        read_reply(&mut socket, &mut buf).await,
+        unknown("nope", "'a b' 'c' ")
+    );
+    // The empty line in between is skipped rather than answered
+    assert_eq!(read_reply(&mut socket, &mut buf).await, unknown("nope", ""));
+}
+
+#[test]
+fn splits_hex_escapes_only_on_hex_digits() {
+    assert_eq!(
+        split_args(br#"set "\x41\x+f\xzz\x4""#).unwrap(),
+        [b"set".to_vec(), b"Ax+fxzzx4".to_vec()]
+    );
+}
+
+#[test]
+fn parses_requests_as_they_arrive() {
+    let limits = RequestLimits {
+        max_multibulk_len: 16,
+        max_bulk_len: 16,
+    };
+    let mut parser = RequestParser::new(limits);
+    let input = b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$5\r\nvalue\r\n*0\r\nPING\r\n";
+
+    // Only what no call has consumed yet is passed in again, so the parser
+    // has to keep everything it needs of a request in between
+    let mut buf = Vec::new();
+    let mut requests = Vec::new();
+    for (i, &byte) in input.iter().enumerate() {
+        buf.push(byte);
+        let (request, consumed) = parser.parse(&buf).unwrap();
+        buf.drain(..consumed);
+        requests.extend(request);
+
+        if i + 1 == b"*3\r\n$3\r\nSET\r\n$1\r\n".len() {
+            assert!(buf.is_empty());
+            assert_eq!(parser.pending_len(), 3);
+        }
+    }
+
+    assert!(buf.is_empty());
+    assert_eq!(parser.pending_len(), 0);
+    assert_eq!(
+        requests,
+        [
+            vec![b"SET".to_vec(), b"k".to_vec(), b"value".to_vec()],
+            vec![],
+            vec![b"PING".to_vec()],
+        ]
+    );
+}
+
+#[tokio::test]
+async fn answers_pipelined_requests_in_order() {
+    let server = start_server().await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    socket
+        .write_all(b"*1\r\n$3\r\none\r\n*1\r\n$3\r\ntwo\r\n*1\r\n$5\r\nthree\r\n")
+        .await
+        .unwrap();
+    for command in ["one", "two", "three"] {
+        assert_eq!(
+            read_reply(&mut socket, &mut buf).await,
+            unknown(command, "")
+        );
+    }
+}
+
+#[tokio::test]
+async fn truncates_arguments_in_unknown_command_errors() {
+    let server = start_server().await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    let name = "n".repeat(200);
+    let args = ["a".repeat(100), "b".repeat(4 * 1024 * 1024), "c".to_owned()];
+    let mut request = format!("*4\r\n${}\r\n{}\r\n", name.len(), name);
+    for arg in &args {
+        request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    socket.write_all(request.as_bytes()).await.unwrap();
+
+    assert_eq!(
+        read_reply(&mut socket, &mut buf).await,
+        unknown(
+            &"n".repeat(128),
+            &format!("'{}' '{}' ", "a".repeat(100), "b".repeat(25))
+        )
+    );
+}
+
+#[tokio::test]
+async fn handles_requests_split_across_reads() {
+    let server = start_server().await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    for chunk in [&b"*2\r\n$4"[..], b"\r\nNO", b"PE\r\n$3\r\nf", b"oo\r\n"] {
+        socket.write_all(chunk).await.unwrap();
+        socket.flush().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(
+        read_reply(&mut socket, &mut buf).await,
+        unknown("NOPE", "'foo' ")
+    );
+}
+
+#[tokio::test]
+async fn parses_slow_requests_in_linear_time() {
+    let server = start_server().await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    // A request with a lot of tiny arguments, trickling in over hundreds of
+    // reads. Parsing it again from the start on every read takes far longer
+    // than the timeout
+    const ARGS: usize = 200_000;
+    let args = b"$1\r\nx\r\n".repeat(ARGS);
+    let reply = within(async {
+        socket
+            .write_all(format!("*{}\r\n$4\r\nNOPE\r\n", ARGS + 1).as_bytes())
+            .await
+            .unwrap();
+        for chunk in args.chunks(args.len() / 500) {
+            socket.write_all(chunk).await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+        read_reply(&mut socket, &mut buf).await
+    })
+    .await;
+
+    assert!(matches!(
+        reply,
+        Frame::Error(e) if e.starts_with("ERR unknown command 'NOPE'")
+    ));
+}
+
+/// Send `request` and expect the given protocol error followed by a hang-up
+async fn assert_protocol_error(config: Option<ServerConfig>, request: &[u8], message: &str) {
+    let server = match config {
+        Some(config) => start_server_with_config(config).await,
+        None => start_server().await,
+    };
+    let mut socket = connect(server.local_addr()).await;
+
+    socket.write_all(request).await.unwrap();
+    let reply = read_to_close(&mut socket).await;
+    assert_eq!(
+        String::from_utf8_lossy(&reply),
+        format!("-ERR Protocol error: {}\r\n", message)
+    );
+}
+
+#[tokio::test]
+async fn rejects_invalid_multibulk_length() {
+    assert_protocol_error(None, b"*abc\r\n", "invalid multibulk length").await;
+}
+
+#[tokio::test]
+async fn rejects_non_bulk_array_elements() {
+    assert_protocol_error(None, b"*1\r\n:1\r\n", "expected '$', got ':'").await;
+}
+
+#[tokio::test]
+async fn rejects_invalid_bulk_length() {
+    assert_protocol_error(None, b"*1\r\n$-5\r\n", "invalid bulk length").await;
+    assert_protocol_error(None, b"*1\r\n$x\r\n", "invalid bulk length").await;
+}
+
+#[tokio::test]
+async fn rejects_unterminated_bulk_strings() {
+    assert_protocol_error(
+        None,
+        b"*1\r\n$3\r\nfooXX",
+        "expected '\\r\\n' after bulk string",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn rejects_unbalanced_inline_quotes() {
+    assert_protocol_error(None, b"set \"foo\r\n", "unbalanced quotes in request").await;
+}
+
+#[tokio::test]
+async fn rejects_too_big_inline_requests() {
+    let request = vec![b'a'; 70 * 1024];
+    assert_protocol_error(None, &request, "too big inline request").await;
+}
+
+#[tokio::test]
+async fn enforces_configured_limits() {
+    let config = || ServerConfig {
+        proto_max_bulk_len: 8,
+        proto_max_multibulk_len: 2,
+        ..ServerConfig::default()
+    };
+
+    assert_protocol_error(
+        Some(config()),
+        b"*3\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n",
+        "invalid multibulk length",
+    )
+    .await;
+    assert_protocol_error(Some(config()), b"*1\r\n$9\r\n", "invalid bulk length").await;
+}
+
+#[tokio::test]
+async fn huge_limits_mean_no_limit() {
+    let mut config = ServerConfig::default();
+    config
+        .set("proto-max-bulk-len", "18446744073709551615")
+        .unwrap();
+    config
+        .set("proto-max-multibulk-len", "18446744073709551615")
+        .unwrap();
+    config
+        .set("client-query-buffer-limit", "18446744073709551615")
+        .unwrap();
+    let server = start_server_with_config(config).await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    socket
+        .write_all(b"*2\r\n$4\r\nnope\r\n$3\r\nfoo\r\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        read_reply(&mut socket, &mut buf).await,
+        unknown("nope", "'foo' ")
+    );
+}
+
+#[tokio::test]
+async fn replies_to_earlier_requests_before_the_protocol_error() {
+    let server = start_server().await;
+    let mut socket = connect(server.local_addr()).await;
+
+    socket
+        .write_all(b"*1\r\n$3\r\none\r\n*x\r\n")
+        .await
+        .unwrap();
+    let reply = read_to_close(&mut socket).await;
+    assert_eq!(
+        String::from_utf8_lossy(&reply),
+        "-ERR unknown command 'one', with args beginning with: \r\n\
+         -ERR Protocol error: invalid multibulk length\r\n"
+    );
+}
+
+#[tokio::test]
+async fn closes_clients_over_the_query_buffer_limit() {
+    let config = ServerConfig {
+        client_query_buffer_limit: 16 * 1024,
+        ..ServerConfig::default()
+    };
+    let server = start_server_with_config(config).await;
+    let mut socket = connect(server.local_addr()).await;
+
+    // Every argument is well within proto-max-bulk-len, but together they
+    // never finish arriving and keep piling up
+    socket.write_all(b"*1000\r\n").await.unwrap();
+    let arg = b"$1024\r\n".iter().chain(&[b'x'; 1024]).chain(b"\r\n");
+    let arg: Vec<u8> = arg.copied().collect();
+    for _ in 0..32 {
+        if socket.write_all(&arg).await.is_err() {
+            break;
+        }
+    }
+
+    // The server hangs up without a reply. It may not have read everything
+    // we sent, in which case the close shows up as a reset
+    let mut reply = Vec::new();
+    match within(socket.read_to_end(&mut reply)).await {
+        Ok(_) => assert!(reply.is_empty()),
+        Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset),
+    }
+}
+
+#[tokio::test]
+async fn delivers_pushes_as_arrays_to_resp2_clients() {
+    let server = start_server().await;
+    let mut socket = connect(server.local_addr()).await;
+    let mut buf = Vec::new();
+
+    eventually(|| server.server().client_ids().len() == 1).await;
+    let id = server.server().client_ids()[0];
+    let message = vec![
+        Frame::Bulk(b"message".to_vec()),
+        Frame::Bulk(b"news".to_vec()),
+        Frame::Null,
+    ];
+    assert!(server.server().push(id, Frame::Push(message.clone())));
+    assert_eq!(
+        read_reply(&mut socket, &mut buf).await,
+        Frame::Array(message)
+    );
+
+    // Replies still work (and come after the push) as usual
+    socket.write_all(b"NOPE\r\n").await.unwrap();
+    assert_eq!(read_reply(&mut socket, &mut buf).await, unknown("NOPE", ""));
+
+    assert!(!server.server().push(id + 1, Frame::Push(Vec::new())));
+}
+
+#[tokio::test]
+async fn disconnects_clients_that_fall_behind_on_pushes() {
+    let server = start_server().await;
+    // Kept open but never read, so the socket buffers fill up and then the
+    // push queue does
+    let _socket = connect(server.local_addr()).await;
+
+    eventually(|| server.server().client_ids().len() == 1).await;
+    let id = server.server().client_ids()[0];
+
+    let frame = Frame::Push(vec![Frame::Bulk(vec![b'x'; 16 * 1024])]);
+    within(async {
+        while server.server().push(id, frame.clone()) {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await;
+
+    // The connection is gone for good, not just unregistered, even though
+    // the writer was stuck on a socket that nobody reads
+    assert!(server.server().client_ids().is_empty());
+    eventually(|| server.server().stats().connected_clients == 0).await;
+    within(server.shutdown()).await.unwrap();
+}
@@ -0,0 +1,139 @@
+use redis_server::{
+    protocol::{Frame, RespVersion, parse_frame},
+    reply::{ReplyBuilder, format_double},
+};
+
+fn encode(frame: &Frame, version: RespVersion) -> Vec<u8> {
+    let mut out = Vec::new();
+    frame.encode(version, &mut out);
+    out
+}
+
+fn sample() -> Frame {
+    Frame::Map(vec![
+        (
+            Frame::Bulk(b"members".to_vec()),
+            Frame::Set(vec![Frame::Bulk(b"a".to_vec()), Frame::Null]),
+        ),
+        (Frame::Simple("score".to_owned()), Frame::Double(1.5)),
+        (
+            Frame::Bulk(b"big".to_vec()),
+            Frame::BigNumber("1".repeat(30)),
+        ),
+        (
+            Frame::Bulk(b"flags".to_vec()),
+            Frame::Array(vec![Frame::Boolean(true), Frame::Boolean(false)]),
+        ),
+        (
+            Frame::Bulk(b"info".to_vec()),
+            Frame::Verbatim {
+                format: "txt".to_owned(),
+                text: b"# Server".to_vec(),
+            },
+        ),
+    ])
+}
+
+#[test]
+fn lowers_resp3_types_for_resp2_clients() {
+    let expected = [
+        "*10\r\n",
+        "$7\r\nmembers\r\n*2\r\n$1\r\na\r\n$-1\r\n",
+        "+score\r\n$3\r\n1.5\r\n",
+        "$3\r\nbig\r\n$30\r\n111111111111111111111111111111\r\n",
+        "$5\r\nflags\r\n*2\r\n:1\r\n:0\r\n",
+        "$4\r\ninfo\r\n$8\r\n# Server\r\n",
+    ]
+    .concat();
+    assert_eq!(
+        String::from_utf8(encode(&sample(), RespVersion::Resp2)).unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn writes_resp3_types_natively() {
+    let expected = [
+        "%5\r\n",
+        "$7\r\nmembers\r\n~2\r\n$1\r\na\r\n_\r\n",
+        "+score\r\n,1.5\r\n",
+        "$3\r\nbig\r\n(111111111111111111111111111111\r\n",
+        "$5\r\nflags\r\n*2\r\n#t\r\n#f\r\n",
+        "$4\r\ninfo\r\n=12\r\ntxt:# Server\r\n",
+    ]
+    .concat();
+    let encoded = encode(&sample(), RespVersion::Resp3);
+    assert_eq!(String::from_utf8(encoded.clone()).unwrap(), expected);
+
+    // And reads back as the same value
+    assert_eq!(
+        parse_frame(&encoded).unwrap(),
+        Some((sample(), encoded.len()))
+    );
+}
+
+#[test]
+fn attributes_are_dropped_for_resp2_clients() {
+    let frame = Frame::Attribute {
+        attributes: vec![(Frame::Bulk(b"ttl".to_vec()), Frame::Integer(100))],
+        value: Box::new(Frame::Bulk(b"value".to_vec())),
+    };
+
+    assert_eq!(encode(&frame, RespVersion::Resp2), b"$5\r\nvalue\r\n");
+
+    let encoded = encode(&frame, RespVersion::Resp3);
+    assert_eq!(encoded, b"|1\r\n$3\r\nttl\r\n:100\r\n$5\r\nvalue\r\n");
+    assert_eq!(parse_frame(&encoded).unwrap(), Some((frame, encoded.len())));
+}
+
+#[test]
+fn nulls_and_pushes_per_version() {
+    let mut out = Vec::new();
+    let mut reply = ReplyBuilder::new(&mut out, RespVersion::Resp2);
+    reply.null_array();
+    reply.push(1);
+    reply.null();
+    assert_eq!(out, b"*-1\r\n*1\r\n$-1\r\n");
+
+    let mut out = Vec::new();
+    let mut reply = ReplyBuilder::new(&mut out, RespVersion::Resp3);
+    reply.null_array();
+    reply.push(1);
+    reply.null();
+    assert_eq!(out, b"_\r\n>1\r\n_\r\n");
+}
+
+#[test]
+fn errors_stay_on_one_line() {
+    let mut out = Vec::new();
+    let mut reply = ReplyBuilder::new(&mut out, RespVersion::Resp2);
+    reply.error("ERR bad\r\nthing");
+    assert_eq!(out, b"-ERR bad  thing\r\n");
+}
+
+#[test]
+fn rolls_back_partial_replies() {
+    let mut out = Vec::new();
+    let mut reply = ReplyBuilder::new(&mut out, RespVersion::Resp2);
+    reply.ok();
+    let checkpoint = reply.checkpoint();
+    reply.array(2);
+    reply.integer(1);
+    reply.rollback(checkpoint);
+    reply.error("ERR internal error");
+    assert_eq!(out, b"+OK\r\n-ERR internal error\r\n");
+}
+
+#[test]
+fn formats_doubles_like_redis() {
+    assert_eq!(format_double(1.5), "1.5");
+    assert_eq!(format_double(10.0), "10");
+    assert_eq!(format_double(-0.25), "-0.25");
+    assert_eq!(format_double(0.1 + 0.2), "0.30000000000000004");
+    assert_eq!(format_double(0.0), "0");
+    assert_eq!(format_double(1e300), "1e+300");
+    assert_eq!(format_double(1.5e-7), "1.5e-07");
+    assert_eq!(format_double(f64::INFINITY), "inf");
+    assert_eq!(format_double(f64::NEG_INFINITY), "-inf");
+    assert_eq!(format_double(f64::NAN), "nan");
+}
@@ -0,0 +1,44 @@
+use redis_server::routing::{CLUSTER_SLOTS, common_slot, crc16, hash_tag, key_slot};
+
+#[test]
+fn crc16_matches_the_reference_check_value() {
+    assert_eq!(crc16(b"123456789"), 0x31c3);
+    assert_eq!(crc16(b""), 0);
+}
+
+#[test]
+fn key_slots_match_redis() {
+    // Values from CLUSTER KEYSLOT on a real Redis
+    assert_eq!(key_slot(b"foo"), 12182);
+    assert_eq!(key_slot(b"bar"), 5061);
+    assert_eq!(key_slot(b"somekey"), 11058);
+    assert_eq!(key_slot(b""), 0);
+    assert!((0..=u8::MAX).all(|b| key_slot(&[b]) < CLUSTER_SLOTS));
+}
+
+#[test]
+fn hash_tags_follow_the_cluster_spec() {
+    assert_eq!(hash_tag(b"{user1000}.following"), b"user1000");
+    assert_eq!(hash_tag(b"foo{bar}{zap}"), b"bar");
+    assert_eq!(hash_tag(b"foo{{bar}}zap"), b"{bar");
+
+    // No tag, or an empty one, means the whole key is hashed
+    assert_eq!(hash_tag(b"foo"), b"foo");
+    assert_eq!(hash_tag(b"foo{}{bar}"), b"foo{}{bar}");
+    assert_eq!(hash_tag(b"foo{bar"), b"foo{bar");
+    assert_eq!(hash_tag(b"foo}bar{"), b"foo}bar{");
+
+    assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"user1000"));
+}
+
+#[test]
+fn common_slot_only_when_every_key_agrees() {
+    let tagged: [&[u8]; 2] = [b"{user1000}.following", b"{user1000}.followers"];
+    assert_eq!(common_slot(tagged), Some(key_slot(b"user1000")));
+
+    let spread: [&[u8]; 2] = [b"foo", b"bar"];
+    assert_eq!(common_slot(spread), None);
+
+    assert_eq!(common_slot([b"foo".as_slice()]), Some(12182));
+    assert_eq!(common_slot([]), None);
+}
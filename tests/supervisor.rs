@@ -0,0 +1,53 @@
+mod common;
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use common::eventually;
+use redis_server::supervisor::Supervisor;
+
+#[tokio::test]
+async fn restarts_tasks_that_panic() {
+    let supervisor = Supervisor::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    let counter = Arc::clone(&runs);
+    supervisor.spawn("flaky", move || {
+        let run = counter.fetch_add(1, Ordering::Relaxed);
+        async move {
+            if run < 2 {
+                panic!("run {} failed", run);
+            }
+            std::future::pending::<()>().await;
+        }
+    });
+
+    eventually(|| runs.load(Ordering::Relaxed) == 3).await;
+    let health = supervisor.health();
+    assert_eq!(health[0].name, "flaky");
+    assert_eq!(health[0].restarts, 2);
+    assert_eq!(health[0].last_panic.as_deref(), Some("run 1 failed"));
+    eventually(|| supervisor.health()[0].running).await;
+
+    supervisor.shutdown();
+    assert!(supervisor.health().is_empty());
+}
+
+#[tokio::test]
+async fn leaves_finished_tasks_alone() {
+    let supervisor = Supervisor::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    let counter = Arc::clone(&runs);
+    supervisor.spawn("oneshot", move || {
+        counter.fetch_add(1, Ordering::Relaxed);
+        async {}
+    });
+
+    eventually(|| !supervisor.health()[0].running && runs.load(Ordering::Relaxed) == 1).await;
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert_eq!(runs.load(Ordering::Relaxed), 1);
+    assert_eq!(supervisor.health()[0].restarts, 0);
+}